@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use strum_macros::{AsRefStr, Display, EnumIter, EnumString};
+
+use crate::feeder::{TokenOrPairAddress, TokenOrPairPriceInfo};
+
+/// Bars built from live ticks.
+pub const AGGREGATED_INTERVALS: [Interval; 5] = [
+    Interval::OneMinute,
+    Interval::FiveMinutes,
+    Interval::FifteenMinutes,
+    Interval::OneHour,
+    Interval::OneDay,
+];
+
+// Closed bars kept per key and interval.
+const DEFAULT_CAPACITY: usize = 500;
+
+/// One OHLCV bar, `time` is the unix timestamp of the bar open in seconds.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+/// Bar size, serialized as the chart API `type`, e.g. `1H`.
+#[derive(
+    Default,
+    AsRefStr,
+    Display,
+    EnumString,
+    EnumIter,
+    Debug,
+    Copy,
+    Clone,
+    Eq,
+    PartialEq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum Interval {
+    #[strum(serialize = "1m")]
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[strum(serialize = "5m")]
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[strum(serialize = "15m")]
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[strum(serialize = "30m")]
+    #[serde(rename = "30m")]
+    ThirtyMinutes,
+    #[default]
+    #[strum(serialize = "1H")]
+    #[serde(rename = "1H")]
+    OneHour,
+    #[strum(serialize = "4H")]
+    #[serde(rename = "4H")]
+    FourHours,
+    #[strum(serialize = "1D")]
+    #[serde(rename = "1D")]
+    OneDay,
+}
+
+impl Interval {
+    pub fn secs(&self) -> u64 {
+        match self {
+            Interval::OneMinute => 60,
+            Interval::FiveMinutes => 5 * 60,
+            Interval::FifteenMinutes => 15 * 60,
+            Interval::ThirtyMinutes => 30 * 60,
+            Interval::OneHour => 60 * 60,
+            Interval::FourHours => 4 * 60 * 60,
+            Interval::OneDay => 24 * 60 * 60,
+        }
+    }
+
+    /// Open time of the bar containing `time`.
+    pub fn floor(&self, time: u64) -> u64 {
+        time - time % self.secs()
+    }
+}
+
+impl Candle {
+    /// A bar that opened and closed at `price`.
+    pub fn flat(time: u64, price: f64) -> Self {
+        Self {
+            time,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: 0.0,
+        }
+    }
+}
+
+/// Bars of one interval, the last one still open.
+#[derive(Debug, Clone)]
+pub struct CandleSeries {
+    interval: Interval,
+    capacity: usize,
+    closed: VecDeque<Candle>,
+    current: Option<Candle>,
+}
+
+impl CandleSeries {
+    pub fn new(interval: Interval, capacity: usize) -> Self {
+        Self {
+            interval,
+            capacity: capacity.max(1),
+            closed: VecDeque::new(),
+            current: None,
+        }
+    }
+
+    /// Adds a tick, returns the bars it closed, oldest first.
+    /// Missing bars in between are filled flat at the last close, late ticks are dropped.
+    pub fn update(&mut self, time: u64, price: f64) -> Vec<Candle> {
+        let open_time = self.interval.floor(time);
+        let Some(current) = self.current.as_mut() else {
+            self.current = Some(Candle::flat(open_time, price));
+            return vec![];
+        };
+
+        if open_time < current.time {
+            return vec![];
+        }
+        if open_time == current.time {
+            current.high = current.high.max(price);
+            current.low = current.low.min(price);
+            current.close = price;
+            return vec![];
+        }
+
+        let last = *current;
+        let secs = self.interval.secs();
+        let gap = (open_time - last.time) / secs - 1;
+        // No point filling more than we keep.
+        let first_fill = gap.saturating_sub(self.capacity as u64) + 1;
+        let mut closed = vec![last];
+        closed.extend((first_fill..=gap).map(|i| Candle::flat(last.time + i * secs, last.close)));
+        for candle in &closed {
+            self.push_closed(*candle);
+        }
+        self.current = Some(Candle::flat(open_time, price));
+
+        closed
+    }
+
+    /// Merges history, e.g. from the chart API, under the open bar.
+    /// Without an open bar the latest history bar becomes it, the next tick closes it.
+    pub fn seed(&mut self, history: &[Candle]) {
+        let open_time = self.current.map(|c| c.time).unwrap_or(u64::MAX);
+        let mut candles: BTreeMap<u64, Candle> = self.closed.iter().map(|c| (c.time, *c)).collect();
+        for candle in history {
+            let time = self.interval.floor(candle.time);
+            if time < open_time {
+                candles.insert(time, Candle { time, ..*candle });
+            }
+        }
+
+        self.closed = candles.into_values().collect();
+        if self.current.is_none() {
+            self.current = self.closed.pop_back();
+        }
+        while self.closed.len() > self.capacity {
+            self.closed.pop_front();
+        }
+    }
+
+    pub fn closed(&self) -> impl Iterator<Item = &Candle> {
+        self.closed.iter()
+    }
+
+    pub fn current(&self) -> Option<&Candle> {
+        self.current.as_ref()
+    }
+
+    /// Closed bars then the open one.
+    pub fn candles(&self) -> Vec<Candle> {
+        self.closed
+            .iter()
+            .chain(self.current.iter())
+            .copied()
+            .collect()
+    }
+
+    fn push_closed(&mut self, candle: Candle) {
+        self.closed.push_back(candle);
+        if self.closed.len() > self.capacity {
+            self.closed.pop_front();
+        }
+    }
+}
+
+/// Builds bars of every interval for every key from the live price stream.
+pub struct CandleAggregator {
+    intervals: Vec<Interval>,
+    capacity: usize,
+    series: HashMap<TokenOrPairAddress, HashMap<Interval, CandleSeries>>,
+}
+
+impl Default for CandleAggregator {
+    fn default() -> Self {
+        Self::new(&AGGREGATED_INTERVALS)
+    }
+}
+
+impl CandleAggregator {
+    pub fn new(intervals: &[Interval]) -> Self {
+        Self {
+            intervals: intervals.to_vec(),
+            capacity: DEFAULT_CAPACITY,
+            series: HashMap::new(),
+        }
+    }
+
+    /// Limits how many closed bars are kept per key and interval.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    fn series_mut(&mut self, key: &str) -> &mut HashMap<Interval, CandleSeries> {
+        let (intervals, capacity) = (&self.intervals, self.capacity);
+        self.series.entry(key.to_owned()).or_insert_with(|| {
+            intervals
+                .iter()
+                .map(|interval| (*interval, CandleSeries::new(*interval, capacity)))
+                .collect()
+        })
+    }
+
+    /// Adds a tick to every interval, returns the bars it closed.
+    pub fn update(&mut self, key: &str, time: u64, price: f64) -> Vec<(Interval, Candle)> {
+        let mut closed = vec![];
+        for (interval, series) in self.series_mut(key) {
+            closed.extend(
+                series
+                    .update(time, price)
+                    .into_iter()
+                    .map(|candle| (*interval, candle)),
+            );
+        }
+        closed
+    }
+
+    /// Adds every fresh token and pair price, returns the bars they closed.
+    /// Failed and stale prices carry nothing new and perps are not prices.
+    pub fn update_prices(
+        &mut self,
+        prices: &HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    ) -> Vec<(TokenOrPairAddress, Interval, Candle)> {
+        let mut closed = vec![];
+        for (key, info) in prices {
+            let price_info = info.price_info();
+            let (false, Some(price), false) = (
+                matches!(info, TokenOrPairPriceInfo::Perp(_)),
+                price_info.price,
+                price_info.is_stale,
+            ) else {
+                continue;
+            };
+
+            closed.extend(
+                self.update(key, price_info.updated_at, price)
+                    .into_iter()
+                    .map(|(interval, candle)| (key.clone(), interval, candle)),
+            );
+        }
+        closed
+    }
+
+    /// Merges history for one key and interval, see `CandleSeries::seed`.
+    pub fn seed(&mut self, key: &str, interval: Interval, history: &[Candle]) {
+        if let Some(series) = self.series_mut(key).get_mut(&interval) {
+            series.seed(history);
+        }
+    }
+
+    pub fn series(&self, key: &str, interval: Interval) -> Option<&CandleSeries> {
+        self.series.get(key)?.get(&interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_interval() {
+        assert_eq!(Interval::from_str("1H").unwrap(), Interval::OneHour);
+        assert_eq!(Interval::FourHours.to_string(), "4H");
+        assert_eq!(Interval::OneDay.secs(), 86_400);
+        assert_eq!(Interval::OneHour.floor(1_736_917_164), 1_736_913_600);
+    }
+
+    #[test]
+    fn test_series_builds_bars_and_fills_gaps() {
+        let mut series = CandleSeries::new(Interval::OneMinute, 10);
+
+        assert!(series.update(60, 10.0).is_empty());
+        assert!(series.update(65, 12.0).is_empty());
+        assert!(series.update(110, 9.0).is_empty());
+        // Late tick from a closed minute.
+        assert!(series.update(30, 100.0).is_empty());
+
+        // Two minutes without ticks.
+        let closed = series.update(245, 11.0);
+        assert_eq!(
+            closed,
+            vec![
+                Candle {
+                    time: 60,
+                    open: 10.0,
+                    high: 12.0,
+                    low: 9.0,
+                    close: 9.0,
+                    volume: 0.0
+                },
+                Candle::flat(120, 9.0),
+                Candle::flat(180, 9.0),
+            ]
+        );
+        assert_eq!(series.current(), Some(&Candle::flat(240, 11.0)));
+        assert_eq!(series.candles().len(), 4);
+    }
+
+    #[test]
+    fn test_series_keeps_capacity_over_long_gaps() {
+        let mut series = CandleSeries::new(Interval::OneMinute, 3);
+        series.update(0, 1.0);
+
+        // A day asleep only fills the bars that fit.
+        let closed = series.update(86_400, 2.0);
+        assert_eq!(closed.len(), 4);
+        assert_eq!(
+            series.closed().map(|c| c.time).collect::<Vec<_>>(),
+            vec![86_220, 86_280, 86_340]
+        );
+    }
+
+    #[test]
+    fn test_seed_then_continue_live() {
+        let mut aggregator = CandleAggregator::new(&[Interval::OneMinute, Interval::FiveMinutes]);
+        let history = (0..5)
+            .map(|i| Candle::flat(i * 60, i as f64))
+            .collect::<Vec<_>>();
+        aggregator.seed("sol", Interval::OneMinute, &history);
+
+        // The last history bar is still open, the next minute closes it.
+        let closed = aggregator.update("sol", 300, 5.0);
+        assert_eq!(closed, vec![(Interval::OneMinute, Candle::flat(240, 4.0))]);
+
+        let series = aggregator.series("sol", Interval::OneMinute).unwrap();
+        assert_eq!(series.closed().count(), 5);
+        assert_eq!(
+            aggregator
+                .series("sol", Interval::FiveMinutes)
+                .unwrap()
+                .current(),
+            Some(&Candle::flat(300, 5.0))
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    feeder::{TokenOrPairAddress, TokenOrPairPriceInfo},
+    store::Tick,
+};
+
+const HOUR_SECS: u64 = 60 * 60;
+const DAY_SECS: u64 = 24 * HOUR_SECS;
+
+// One sample a minute is plenty for hourly and daily changes.
+const SAMPLE_SECS: u64 = 60;
+
+/// Percent moves that pick each trend arrow, e.g.
+///
+/// ```yaml
+/// change:
+///   strong_up_pct: 2.0
+///   up_pct: 0.5
+///   down_pct: -0.5
+///   strong_down_pct: -2.0
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ChangeSettings {
+    pub strong_up_pct: f64,
+    pub up_pct: f64,
+    pub down_pct: f64,
+    pub strong_down_pct: f64,
+}
+
+impl Default for ChangeSettings {
+    fn default() -> Self {
+        Self {
+            strong_up_pct: 2.0,
+            up_pct: 0.5,
+            down_pct: -0.5,
+            strong_down_pct: -2.0,
+        }
+    }
+}
+
+impl ChangeSettings {
+    pub fn trend(&self, pct: f64) -> Trend {
+        if pct >= self.strong_up_pct {
+            Trend::StrongUp
+        } else if pct >= self.up_pct {
+            Trend::Up
+        } else if pct <= self.strong_down_pct {
+            Trend::StrongDown
+        } else if pct <= self.down_pct {
+            Trend::Down
+        } else {
+            Trend::Flat
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trend {
+    StrongUp,
+    Up,
+    Flat,
+    Down,
+    StrongDown,
+}
+
+impl Trend {
+    pub fn mirrored(&self) -> Self {
+        match self {
+            Trend::StrongUp => Trend::StrongDown,
+            Trend::Up => Trend::Down,
+            Trend::Flat => Trend::Flat,
+            Trend::Down => Trend::Up,
+            Trend::StrongDown => Trend::StrongUp,
+        }
+    }
+
+    pub fn glyph(&self) -> &'static str {
+        match self {
+            Trend::StrongUp => "↑",
+            Trend::Up => "↗︎",
+            Trend::Flat => "→",
+            Trend::Down => "↘︎",
+            Trend::StrongDown => "↓",
+        }
+    }
+}
+
+/// Percent moves of a key, `None` until there is enough history.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceChange {
+    pub pct_1h: Option<f64>,
+    pub pct_24h: Option<f64>,
+    pub pct_since_start: Option<f64>,
+    // Of the longest window known, 24h then 1h then since start.
+    pub trend: Option<Trend>,
+}
+
+impl PriceChange {
+    /// The longest window known, e.g. `("24h", 1.2)`.
+    pub fn headline(&self) -> Option<(&'static str, f64)> {
+        self.pct_24h
+            .map(|pct| ("24h", pct))
+            .or(self.pct_1h.map(|pct| ("1h", pct)))
+            .or(self.pct_since_start.map(|pct| ("start", pct)))
+    }
+
+    /// The same moves seen from the inverted pair, arrows mirrored.
+    pub fn inverted(&self) -> Self {
+        let invert = |pct: f64| (100.0 / (100.0 + pct) - 1.0) * 100.0;
+        Self {
+            pct_1h: self.pct_1h.map(invert),
+            pct_24h: self.pct_24h.map(invert),
+            pct_since_start: self.pct_since_start.map(invert),
+            trend: self.trend.map(|trend| trend.mirrored()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct History {
+    // Oldest first, at least `SAMPLE_SECS` apart.
+    samples: VecDeque<(u64, f64)>,
+    // First price seen since the app started.
+    start: Option<f64>,
+}
+
+impl History {
+    fn record(&mut self, time: u64, price: f64) {
+        match self.samples.back() {
+            Some((last_time, _)) if time < last_time + SAMPLE_SECS => {}
+            _ => self.samples.push_back((time, price)),
+        }
+        while self
+            .samples
+            .front()
+            .is_some_and(|(oldest, _)| *oldest + DAY_SECS + SAMPLE_SECS < time)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The last price at or before `time - window`.
+    fn price_ago(&self, time: u64, window: u64) -> Option<f64> {
+        let then = time.checked_sub(window)?;
+        self.samples
+            .iter()
+            .rev()
+            .find(|(sample_time, _)| *sample_time <= then)
+            .map(|(_, price)| *price)
+    }
+}
+
+fn pct_change(from: f64, to: f64) -> Option<f64> {
+    (from != 0.0).then(|| (to - from) / from.abs() * 100.0)
+}
+
+/// Rolling 1h and 24h changes and the change since start for every key.
+#[derive(Debug, Default)]
+pub struct ChangeTracker {
+    settings: ChangeSettings,
+    histories: HashMap<TokenOrPairAddress, History>,
+}
+
+impl ChangeTracker {
+    pub fn new(settings: ChangeSettings) -> Self {
+        Self {
+            settings,
+            histories: HashMap::new(),
+        }
+    }
+
+    /// Loads earlier ticks, e.g. from the price store, they don't count as the start price.
+    pub fn seed(&mut self, key: &str, ticks: &[Tick]) {
+        let history = self.histories.entry(key.to_owned()).or_default();
+        for tick in ticks {
+            history.record(tick.time, tick.price);
+        }
+    }
+
+    /// Records a price and returns the changes up to it.
+    pub fn update(&mut self, key: &str, time: u64, price: f64) -> PriceChange {
+        let history = self.histories.entry(key.to_owned()).or_default();
+        let start = *history.start.get_or_insert(price);
+        history.record(time, price);
+
+        let mut change = PriceChange {
+            pct_1h: history
+                .price_ago(time, HOUR_SECS)
+                .and_then(|then| pct_change(then, price)),
+            pct_24h: history
+                .price_ago(time, DAY_SECS)
+                .and_then(|then| pct_change(then, price)),
+            pct_since_start: pct_change(start, price),
+            trend: None,
+        };
+        change.trend = change.headline().map(|(_, pct)| self.settings.trend(pct));
+        change
+    }
+
+    /// Sets the change of every priced key but perps.
+    pub fn attach_changes(
+        &mut self,
+        prices: &mut HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    ) {
+        for (key, info) in prices.iter_mut() {
+            // PnL hovers around zero, a percent of it means nothing.
+            if let TokenOrPairPriceInfo::Perp(_) = info {
+                continue;
+            }
+            let price_info = info.price_info_mut();
+            if let Some(price) = price_info.price {
+                price_info.change = Some(self.update(key, price_info.updated_at, price));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feeder::{PerpValueInfo, PriceInfo};
+
+    #[test]
+    fn test_trend_thresholds() {
+        let settings = ChangeSettings::default();
+        assert_eq!(settings.trend(3.0), Trend::StrongUp);
+        assert_eq!(settings.trend(0.5), Trend::Up);
+        assert_eq!(settings.trend(0.1), Trend::Flat);
+        assert_eq!(settings.trend(-1.0), Trend::Down);
+        assert_eq!(settings.trend(-2.0), Trend::StrongDown);
+        assert_eq!(Trend::Up.glyph(), "↗︎");
+    }
+
+    #[test]
+    fn test_changes_need_enough_history() {
+        let mut tracker = ChangeTracker::default();
+
+        let change = tracker.update("sol", 0, 100.0);
+        assert_eq!(change.pct_since_start, Some(0.0));
+        assert_eq!(change.pct_1h, None);
+        assert_eq!(change.trend, Some(Trend::Flat));
+
+        // Half an hour isn't an hourly change yet.
+        assert_eq!(tracker.update("sol", 1_800, 101.0).pct_1h, None);
+
+        let change = tracker.update("sol", 3_600, 102.0);
+        assert!((change.pct_1h.unwrap() - 2.0).abs() < 1e-9);
+        assert_eq!(change.pct_24h, None);
+        assert_eq!(change.headline(), Some(("1h", change.pct_1h.unwrap())));
+        assert_eq!(change.trend, Some(Trend::StrongUp));
+    }
+
+    #[test]
+    fn test_seeded_history_gives_daily_change() {
+        let mut tracker = ChangeTracker::default();
+        let ticks = (0..=24)
+            .map(|hour| Tick {
+                key: "jlp".to_owned(),
+                time: hour * HOUR_SECS,
+                price: 4.0,
+                source: None,
+            })
+            .collect::<Vec<_>>();
+        tracker.seed("jlp", &ticks);
+
+        let change = tracker.update("jlp", DAY_SECS + 60, 3.96);
+        assert!((change.pct_24h.unwrap() + 1.0).abs() < 1e-9);
+        assert!((change.pct_1h.unwrap() + 1.0).abs() < 1e-9);
+        // Seeded ticks are history, the app started at this price.
+        assert_eq!(change.pct_since_start, Some(0.0));
+        assert_eq!(change.trend, Some(Trend::Down));
+    }
+
+    #[test]
+    fn test_perps_pnl_has_no_change() {
+        let mut tracker = ChangeTracker::default();
+        let pnl = |price: f64| {
+            TokenOrPairPriceInfo::Perp(PerpValueInfo {
+                pnl_after_fees_usd: PriceInfo {
+                    price: Some(price),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+
+        for price in [-1.0, 5.0] {
+            let mut prices = HashMap::from([("sol_PERPS".to_owned(), pnl(price))]);
+            tracker.attach_changes(&mut prices);
+            assert_eq!(prices["sol_PERPS"].price_info().change, None);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use chrono::Utc;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+use tokio::time::{Duration, Instant};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    // Requests flow normally.
+    Closed,
+    // Too many failures, requests are rejected without hitting the network.
+    Open,
+    // Cool down is over, a single trial request decides whether to close or open again.
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerSettings {
+    pub failure_threshold: u32,
+    pub open_secs: u64,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_secs: 30,
+        }
+    }
+}
+
+/// Health of one upstream host, as exposed to the frontend.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EndpointHealth {
+    pub endpoint: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub total_requests: u64,
+    pub total_failures: u64,
+    pub last_error: Option<String>,
+    // Unix millis.
+    pub last_success_at: Option<i64>,
+    pub last_failure_at: Option<i64>,
+}
+
+impl EndpointHealth {
+    pub fn is_degraded(&self) -> bool {
+        self.state != CircuitState::Closed
+    }
+}
+
+struct Breaker {
+    health: EndpointHealth,
+    opened_at: Option<Instant>,
+    // When the half open trial went out, others wait for it.
+    trial_started_at: Option<Instant>,
+}
+
+/// One circuit breaker per upstream host.
+#[derive(Default)]
+pub struct CircuitBreakers {
+    settings: CircuitBreakerSettings,
+    breakers: Mutex<HashMap<String, Breaker>>,
+}
+
+impl CircuitBreakers {
+    pub fn new(settings: CircuitBreakerSettings) -> Self {
+        Self {
+            settings,
+            breakers: Default::default(),
+        }
+    }
+
+    /// Lets the request through, or returns how long until the circuit may close.
+    pub fn check(&self, endpoint: &str) -> Result<(), Duration> {
+        let mut breakers = self.breakers.lock().unwrap();
+        let breaker = breakers
+            .entry(endpoint.to_owned())
+            .or_insert_with(|| Breaker {
+                health: EndpointHealth {
+                    endpoint: endpoint.to_owned(),
+                    state: CircuitState::Closed,
+                    consecutive_failures: 0,
+                    total_requests: 0,
+                    total_failures: 0,
+                    last_error: None,
+                    last_success_at: None,
+                    last_failure_at: None,
+                },
+                opened_at: None,
+                trial_started_at: None,
+            });
+
+        if breaker.health.state == CircuitState::Open {
+            let open_duration = Duration::from_secs(self.settings.open_secs);
+            let elapsed = breaker.opened_at.map(|at| at.elapsed()).unwrap_or_default();
+            if elapsed < open_duration {
+                return Err(open_duration - elapsed);
+            }
+            breaker.health.state = CircuitState::HalfOpen;
+        } else if breaker.health.state == CircuitState::HalfOpen {
+            // A trial that never reported back, e.g. dropped, gives way after a cool down.
+            let open_duration = Duration::from_secs(self.settings.open_secs);
+            let elapsed = breaker
+                .trial_started_at
+                .map(|at| at.elapsed())
+                .unwrap_or(open_duration);
+            if elapsed < open_duration {
+                return Err(open_duration - elapsed);
+            }
+        }
+
+        if breaker.health.state == CircuitState::HalfOpen {
+            breaker.trial_started_at = Some(Instant::now());
+        }
+        breaker.health.total_requests += 1;
+        Ok(())
+    }
+
+    pub fn record_success(&self, endpoint: &str) {
+        let mut breakers = self.breakers.lock().unwrap();
+        if let Some(breaker) = breakers.get_mut(endpoint) {
+            breaker.health.state = CircuitState::Closed;
+            breaker.health.consecutive_failures = 0;
+            breaker.health.last_success_at = Some(Utc::now().timestamp_millis());
+            breaker.opened_at = None;
+            breaker.trial_started_at = None;
+        }
+    }
+
+    pub fn record_failure(&self, endpoint: &str, error: &str) {
+        let mut breakers = self.breakers.lock().unwrap();
+        if let Some(breaker) = breakers.get_mut(endpoint) {
+            let health = &mut breaker.health;
+            health.consecutive_failures += 1;
+            health.total_failures += 1;
+            health.last_error = Some(error.to_owned());
+            health.last_failure_at = Some(Utc::now().timestamp_millis());
+
+            // A failed trial reopens right away.
+            if health.state == CircuitState::HalfOpen
+                || health.consecutive_failures >= self.settings.failure_threshold
+            {
+                health.state = CircuitState::Open;
+                breaker.opened_at = Some(Instant::now());
+            }
+            breaker.trial_started_at = None;
+        }
+    }
+
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        let breakers = self.breakers.lock().unwrap();
+        let mut health = breakers
+            .values()
+            .map(|breaker| breaker.health.clone())
+            .collect::<Vec<_>>();
+        health.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+        health
+    }
+}
+
+/// Host and port of a URL, which is what a breaker tracks.
+pub fn endpoint_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.host_str().map(|host| match url.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host.to_owned(),
+            })
+        })
+        .unwrap_or_else(|| url.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_of(breakers: &CircuitBreakers, endpoint: &str) -> CircuitState {
+        breakers
+            .health()
+            .into_iter()
+            .find(|health| health.endpoint == endpoint)
+            .unwrap()
+            .state
+    }
+
+    /// As if `by` went by since the circuit opened or the trial went out.
+    fn rewind(breakers: &CircuitBreakers, endpoint: &str, by: Duration) {
+        let mut breakers = breakers.breakers.lock().unwrap();
+        let breaker = breakers.get_mut(endpoint).unwrap();
+        breaker.opened_at = breaker.opened_at.map(|at| at - by);
+        breaker.trial_started_at = breaker.trial_started_at.map(|at| at - by);
+    }
+
+    #[test]
+    fn test_endpoint_of() {
+        assert_eq!(
+            endpoint_of("https://api.jup.ag/price/v2?ids=x"),
+            "api.jup.ag"
+        );
+        assert_eq!(endpoint_of("http://127.0.0.1:8080/x"), "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn test_opens_after_threshold_and_closes_after_trial() {
+        let breakers = CircuitBreakers::new(CircuitBreakerSettings {
+            failure_threshold: 2,
+            open_secs: 0,
+        });
+        let endpoint = "api.jup.ag";
+
+        assert!(breakers.check(endpoint).is_ok());
+        breakers.record_failure(endpoint, "timeout");
+        assert_eq!(state_of(&breakers, endpoint), CircuitState::Closed);
+
+        assert!(breakers.check(endpoint).is_ok());
+        breakers.record_failure(endpoint, "timeout");
+        assert_eq!(state_of(&breakers, endpoint), CircuitState::Open);
+        assert!(breakers.health()[0].is_degraded());
+
+        // Zero cool down, so the next check is the half open trial.
+        assert!(breakers.check(endpoint).is_ok());
+        assert_eq!(state_of(&breakers, endpoint), CircuitState::HalfOpen);
+        breakers.record_success(endpoint);
+
+        let health = &breakers.health()[0];
+        assert_eq!(health.state, CircuitState::Closed);
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.total_failures, 2);
+        assert_eq!(health.total_requests, 3);
+    }
+
+    #[test]
+    fn test_half_open_lets_a_single_trial_through() {
+        let breakers = CircuitBreakers::new(CircuitBreakerSettings {
+            failure_threshold: 1,
+            open_secs: 30,
+        });
+        let endpoint = "api.jup.ag";
+
+        assert!(breakers.check(endpoint).is_ok());
+        breakers.record_failure(endpoint, "timeout");
+        rewind(&breakers, endpoint, Duration::from_secs(30));
+
+        // First caller is the trial, the others wait for its outcome.
+        assert!(breakers.check(endpoint).is_ok());
+        assert!(breakers.check(endpoint).is_err());
+        assert!(breakers.check(endpoint).is_err());
+        assert_eq!(breakers.health()[0].total_requests, 2);
+
+        breakers.record_success(endpoint);
+        assert!(breakers.check(endpoint).is_ok());
+        assert!(breakers.check(endpoint).is_ok());
+
+        // A trial that never reports back gives way after a cool down.
+        breakers.record_failure(endpoint, "timeout");
+        rewind(&breakers, endpoint, Duration::from_secs(30));
+        assert!(breakers.check(endpoint).is_ok());
+        assert!(breakers.check(endpoint).is_err());
+        rewind(&breakers, endpoint, Duration::from_secs(30));
+        assert!(breakers.check(endpoint).is_ok());
+        assert_eq!(state_of(&breakers, endpoint), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn test_open_circuit_rejects_until_cool_down() {
+        let breakers = CircuitBreakers::new(CircuitBreakerSettings {
+            failure_threshold: 1,
+            open_secs: 60,
+        });
+        let endpoint = "api-v3.raydium.io";
+
+        assert!(breakers.check(endpoint).is_ok());
+        breakers.record_failure(endpoint, "502");
+
+        let retry_in = breakers.check(endpoint).unwrap_err();
+        assert!(retry_in > Duration::from_secs(50));
+        assert_eq!(breakers.health()[0].total_requests, 1);
+    }
+}
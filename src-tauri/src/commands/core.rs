@@ -1,12 +1,17 @@
 use std::collections::HashMap;
 
 use crate::assets::read_local_image;
+use crate::circuit_breaker::EndpointHealth;
 use crate::feeder::{TokenOrPairAddress, TokenOrPairPriceInfo};
-use crate::jup::prices::PriceFetcher;
-use crate::token_registry::{get_pair_ot_token_address_from_tokens, Token};
+use crate::forecast::{forecast_tokens, ForecastReport};
+use crate::jup::charts::CandleFetcher;
+use crate::ray::RaydiumFetcher;
+use crate::store::Tick;
+use crate::ta::TaSnapshot;
+use crate::token_registry::{get_pair_ot_token_address_from_tokens, Token, INVERTED_PAIRS_FILE};
+use crate::volatility::{pair_range_stability, RangeStability};
 use crate::{AppState, SelectedTokenOrPair};
-use log::warn;
-use tauri::Manager;
+use tauri::{AppHandle, Manager, State};
 use tokio::sync::watch;
 
 #[tauri::command]
@@ -14,19 +19,155 @@ pub fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
+/// Circuit breaker state of every upstream, for the frontend to show what is degraded.
+#[tauri::command]
+pub fn get_upstream_health(state: State<'_, AppState>) -> Vec<EndpointHealth> {
+    state.fetcher.lock().unwrap().health()
+}
+
+/// Stored ticks of a token, pair or perp key, oldest first.
+#[tauri::command]
+pub fn get_price_history(
+    state: State<'_, AppState>,
+    key: String,
+    time_from: u64,
+    time_to: u64,
+) -> Result<Vec<Tick>, String> {
+    let price_store = state
+        .price_store
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or("Price history is not available")?;
+    price_store
+        .query(&key, time_from, time_to)
+        .map_err(|e| e.to_string())
+}
+
+/// Latest indicators of every token and pair.
+#[tauri::command]
+pub fn get_indicators(state: State<'_, AppState>) -> HashMap<TokenOrPairAddress, TaSnapshot> {
+    let Some(price_sender) = state.price_sender.lock().unwrap().clone() else {
+        return HashMap::new();
+    };
+    let prices = price_sender.borrow();
+    prices
+        .iter()
+        .filter_map(|(address, info)| Some((address.clone(), info.price_info().ta.clone()?)))
+        .collect()
+}
+
+/// Day ahead forecast of a token or pair key with its walk-forward accuracy.
+#[tauri::command]
+pub async fn get_forecast(
+    state: State<'_, AppState>,
+    key: String,
+) -> Result<ForecastReport, String> {
+    let tokens = {
+        let token_registry = state.token_registry.lock().unwrap();
+        match token_registry.get_by_address(&key) {
+            Some(token) => vec![token.clone()],
+            None => {
+                let (pair, is_inverted) = token_registry
+                    .find_pair(&key)
+                    .ok_or(format!("Not a configured token or pair: {}", key))?;
+                let [base, quote] = pair.clone();
+                if is_inverted {
+                    vec![quote, base]
+                } else {
+                    vec![base, quote]
+                }
+            }
+        }
+    };
+    let candle_fetcher = CandleFetcher::with_fetcher(
+        state.fetcher.lock().unwrap().clone(),
+        &state.api_config.lock().unwrap().jup_charts_api,
+    );
+    let price_store = state.price_store.lock().unwrap().clone();
+
+    forecast_tokens(&candle_fetcher, price_store.as_deref(), &key, &tokens)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Chance a configured pair stays within `range_pct` of its price for `days`.
+#[tauri::command]
+pub async fn get_range_stability(
+    state: State<'_, AppState>,
+    key: String,
+    range_pct: f64,
+    days: f64,
+) -> Result<RangeStability, String> {
+    let pair = {
+        let token_registry = state.token_registry.lock().unwrap();
+        let (pair, is_inverted) = token_registry
+            .find_pair(&key)
+            .ok_or(format!("Not a configured pair: {}", key))?;
+        let [base, quote] = pair.clone();
+        if is_inverted {
+            [quote, base]
+        } else {
+            [base, quote]
+        }
+    };
+    let fetcher = state.fetcher.lock().unwrap().clone();
+    let api_config = state.api_config.lock().unwrap().clone();
+    let candle_fetcher = CandleFetcher::with_fetcher(fetcher.clone(), &api_config.jup_charts_api);
+    let raydium_fetcher = RaydiumFetcher::with_fetcher(fetcher, &api_config.raydium_api);
+
+    pair_range_stability(&candle_fetcher, &raydium_fetcher, &pair, range_pct, days)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Flips how a pair is shown and remembers it, returns the address it is shown as now.
+#[tauri::command]
+pub fn flip_pair(app_handle: AppHandle, key: String) -> Result<String, String> {
+    let state = app_handle.state::<AppState>();
+    let path = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(INVERTED_PAIRS_FILE);
+    let display_address = {
+        let mut token_registry = state.token_registry.lock().unwrap();
+        let display_address = token_registry
+            .toggle_pair_inversion(&key)
+            .map_err(|e| e.to_string())?;
+        token_registry
+            .save_inverted_pairs(&path)
+            .map_err(|e| e.to_string())?;
+        display_address
+    };
+
+    // Redraw the tray with the prices we have.
+    if let Some(price_sender) = state.price_sender.lock().unwrap().as_ref() {
+        price_sender.send_modify(|_| {});
+    }
+
+    Ok(display_address)
+}
+
 #[tauri::command]
 pub fn update_token_and_price(
     app_handle: tauri::AppHandle,
     selected_tokens: Vec<Token>,
     price_sender: &watch::Sender<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>>,
 ) -> anyhow::Result<()> {
+    if selected_tokens.is_empty() {
+        anyhow::bail!("No token selected");
+    }
+
     // Update selected token
     let state = app_handle.state::<AppState>();
     *state.selected_tokens.lock().unwrap() = selected_tokens.clone();
 
     // Update tray icon and title
     let is_pair = selected_tokens.len() == 2;
-    let icon_path = if !is_pair {
+    let icon_path = if selected_tokens[0].address.ends_with("_PERPS") {
+        format!("./tokens/{}_PERPS.png", selected_tokens[0].symbol)
+    } else if !is_pair {
         format!("./tokens/{}.png", selected_tokens[0].symbol)
     } else {
         let pair_symbol = format!(
@@ -41,7 +182,9 @@ pub fn update_token_and_price(
         address: selected_token_or_pair_address.clone(),
     };
 
-    let icon = read_local_image(&icon_path)?;
+    // Synthetic pairs and most perps markets have no icon of their own.
+    let icon = read_local_image(&icon_path)
+        .or_else(|_| read_local_image(&format!("./tokens/{}.png", selected_tokens[0].symbol)))?;
 
     let tray_id = {
         state
@@ -58,35 +201,8 @@ pub fn update_token_and_price(
     // Loading
     tray_icon.set_title(Some("…"))?;
 
-    // Instant fetch price
-    let price_sender_clone = price_sender.clone();
-    let is_pair: bool = selected_tokens.len() == 2;
-    let single_tokens = if is_pair {
-        vec![]
-    } else {
-        selected_tokens.clone()
-    };
-    let pair_tokens = if is_pair {
-        vec![[selected_tokens[0].clone(), selected_tokens[1].clone()]]
-    } else {
-        vec![]
-    };
-
-    tauri::async_runtime::spawn(async move {
-        // TODO: perps is more complex, should we wait?
-        let price_fetcher = PriceFetcher::new();
-        match price_fetcher
-            .fetch_many_price_and_format(single_tokens, pair_tokens)
-            .await
-        {
-            Some(prices_map) => {
-                let _ = price_sender_clone.send(prices_map);
-            }
-            None => {
-                warn!("Price fetch failed.");
-            }
-        };
-    });
+    // Redraw from the runner's prices, they already fall back to the last good value.
+    price_sender.send_modify(|_| {});
 
     // let tray_menu = state
     //     .tray_menu
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    change::ChangeSettings,
+    circuit_breaker::CircuitBreakerSettings,
+    divergence::DivergenceSettings,
+    jup::{
+        charts::CHARTS_API,
+        perps::PERPS_API_BASE,
+        prices::{TokenSymbol, JUP_API},
+        quote::JUP_QUOTE_API,
+    },
+    liquidation::LiquidationSettings,
+    ray::RAYDIUM_BASE_API,
+    solana::SOLANA_RPC,
+    store::RetentionPolicy,
+};
+
+// Define a struct to deserialize the YAML into
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Config {
+    pub app_name: String,
+    pub version: String,
+    pub settings: Settings,
+    pub wallets: Vec<Wallet>,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    #[serde(flatten)]
+    pub monitors: MonitorSettings,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Wallet {
+    pub name: String,
+    pub public_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Settings {
+    pub theme: String,
+    pub debug: bool,
+}
+
+/// Upstream endpoints and the shared HTTP client setup, e.g.
+///
+/// ```yaml
+/// api:
+///   jup_price_api: https://api.jup.ag/price/v2
+///   api_key: my-key
+///   proxy: http://proxy.local:3128
+///   headers:
+///     x-team: catbot
+///   circuit_breaker:
+///     failure_threshold: 5
+///     open_secs: 30
+///   quote:
+///     mode: quote
+///     notionals:
+///       SOL: 10
+///       USDC: 1000
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub jup_price_api: String,
+    pub jup_quote_api: String,
+    pub jup_perps_api: String,
+    pub jup_charts_api: String,
+    pub raydium_api: String,
+    pub solana_rpc_url: String,
+    // Streams pool accounts over `accountSubscribe` when set, e.g. wss://api.mainnet-beta.solana.com
+    pub solana_ws_url: Option<String>,
+    // Sent as `x-api-key`, e.g. for the Jupiter pro API.
+    pub api_key: Option<String>,
+    pub user_agent: String,
+    pub proxy: Option<String>,
+    pub headers: HashMap<String, String>,
+    pub cache: CacheConfig,
+    pub circuit_breaker: CircuitBreakerSettings,
+    pub quote: QuoteConfig,
+}
+
+/// What the runner watches on top of prices, each at the top level of the config.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MonitorSettings {
+    pub divergence: DivergenceSettings,
+    pub change: ChangeSettings,
+    pub liquidation: LiquidationSettings,
+}
+
+/// Which price the tray shows.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceMode {
+    // Mid price from the price API.
+    #[default]
+    Mid,
+    // What a trade of the configured size would fill at.
+    Quote,
+}
+
+/// Trade size used for quote-based prices.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct QuoteConfig {
+    pub mode: PriceMode,
+    pub slippage_bps: u16,
+    // Amount of the quote token to spend, e.g. `SOL: 10` prices JLP/SOL by buying JLP with 10 SOL.
+    // Single tokens are quoted in USDC.
+    pub notionals: HashMap<TokenSymbol, f64>,
+}
+
+impl Default for QuoteConfig {
+    fn default() -> Self {
+        Self {
+            mode: PriceMode::Mid,
+            slippage_bps: 50,
+            notionals: HashMap::from([(TokenSymbol::SOL, 10.0), (TokenSymbol::USDC, 1_000.0)]),
+        }
+    }
+}
+
+/// How long responses stay fresh per endpoint, zero turns caching off.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub jup_price_ttl_ms: u64,
+    pub jup_perps_ttl_ms: u64,
+    pub raydium_ttl_ms: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        // Just under the 5 seconds poll, so the menu reuses the runner's numbers.
+        Self {
+            jup_price_ttl_ms: 4_000,
+            jup_perps_ttl_ms: 4_000,
+            raydium_ttl_ms: 10_000,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn jup_price_ttl(&self) -> Duration {
+        Duration::from_millis(self.jup_price_ttl_ms)
+    }
+
+    pub fn jup_perps_ttl(&self) -> Duration {
+        Duration::from_millis(self.jup_perps_ttl_ms)
+    }
+
+    pub fn raydium_ttl(&self) -> Duration {
+        Duration::from_millis(self.raydium_ttl_ms)
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            jup_price_api: JUP_API.to_owned(),
+            jup_quote_api: JUP_QUOTE_API.to_owned(),
+            jup_perps_api: PERPS_API_BASE.to_owned(),
+            jup_charts_api: CHARTS_API.to_owned(),
+            raydium_api: RAYDIUM_BASE_API.to_owned(),
+            solana_rpc_url: SOLANA_RPC.to_owned(),
+            solana_ws_url: None,
+            api_key: None,
+            user_agent: format!("CatBot/{}", env!("CARGO_PKG_VERSION")),
+            proxy: None,
+            headers: HashMap::new(),
+            cache: CacheConfig::default(),
+            circuit_breaker: CircuitBreakerSettings::default(),
+            quote: QuoteConfig::default(),
+        }
+    }
+}
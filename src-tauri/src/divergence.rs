@@ -0,0 +1,252 @@
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use strum::IntoEnumIterator;
+
+use crate::{
+    feeder::TokenOrPairAddress,
+    jup::prices::PriceFetcher,
+    ray::{PoolId, RaydiumFetcher},
+    time::get_unix_timestamp,
+    token_registry::{get_pair_ot_token_address_from_tokens, Token},
+};
+
+/// When venues disagree enough to say so, e.g.
+///
+/// ```yaml
+/// divergence:
+///   threshold_bps: 50
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DivergenceSettings {
+    pub threshold_bps: f64,
+    // Spreads kept per pair.
+    pub history: usize,
+}
+
+impl Default for DivergenceSettings {
+    fn default() -> Self {
+        Self {
+            threshold_bps: 50.0,
+            history: 720,
+        }
+    }
+}
+
+/// Raydium against Jupiter for one pair at one time.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Spread {
+    pub time: u64,
+    pub jupiter: f64,
+    pub raydium: f64,
+    // Positive when Raydium is higher, relative to the mid of both.
+    pub bps: f64,
+}
+
+impl Spread {
+    pub fn new(time: u64, jupiter: f64, raydium: f64) -> Self {
+        let mid = (jupiter + raydium) / 2.0;
+        let bps = if mid == 0.0 {
+            0.0
+        } else {
+            (raydium - jupiter) / mid * 10_000.0
+        };
+
+        Self {
+            time,
+            jupiter,
+            raydium,
+            bps,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum DivergenceEvent {
+    // The spread went past the threshold.
+    Diverged {
+        key: TokenOrPairAddress,
+        label: String,
+        spread: Spread,
+    },
+    // Back under the threshold.
+    Converged {
+        key: TokenOrPairAddress,
+        label: String,
+        spread: Spread,
+    },
+}
+
+/// Tracks spreads per pair and raises an event each time one crosses the threshold.
+pub struct DivergenceMonitor {
+    settings: DivergenceSettings,
+    history: HashMap<TokenOrPairAddress, VecDeque<Spread>>,
+    diverged: HashSet<TokenOrPairAddress>,
+}
+
+impl DivergenceMonitor {
+    pub fn new(settings: DivergenceSettings) -> Self {
+        Self {
+            settings,
+            history: HashMap::new(),
+            diverged: HashSet::new(),
+        }
+    }
+
+    pub fn record(&mut self, key: &str, label: &str, spread: Spread) -> Option<DivergenceEvent> {
+        let history = self.history.entry(key.to_owned()).or_default();
+        history.push_back(spread);
+        if history.len() > self.settings.history.max(1) {
+            history.pop_front();
+        }
+
+        let is_diverged = spread.bps.abs() > self.settings.threshold_bps;
+        let (key, label) = (key.to_owned(), label.to_owned());
+        match (is_diverged, self.diverged.contains(&key)) {
+            (true, false) => {
+                self.diverged.insert(key.clone());
+                Some(DivergenceEvent::Diverged { key, label, spread })
+            }
+            (false, true) => {
+                self.diverged.remove(&key);
+                Some(DivergenceEvent::Converged { key, label, spread })
+            }
+            _ => None,
+        }
+    }
+
+    /// Spreads of a pair, oldest first.
+    pub fn history(&self, key: &str) -> impl Iterator<Item = &Spread> {
+        self.history.get(key).into_iter().flatten()
+    }
+}
+
+/// Asks both venues for the same pairs.
+pub struct VenuePrices {
+    jupiter: PriceFetcher,
+    raydium: RaydiumFetcher,
+}
+
+impl VenuePrices {
+    pub fn new(jupiter: PriceFetcher, raydium: RaydiumFetcher) -> Self {
+        Self { jupiter, raydium }
+    }
+
+    /// Spreads of the pairs traded by a known Raydium pool, pairs either venue misses are left out.
+    pub async fn fetch_spreads(
+        &self,
+        pairs: &[[Token; 2]],
+    ) -> Result<HashMap<TokenOrPairAddress, Spread>> {
+        let pools = PoolId::iter().collect::<Vec<_>>();
+        let pools = self.raydium.fetch_pool_infos_by_ids(&pools).await?;
+        let time = get_unix_timestamp();
+
+        let mut spreads = HashMap::new();
+        for pair in pairs {
+            let [base, quote] = pair;
+            let Some(raydium) = pools
+                .iter()
+                .find_map(|pool| pool.price_of(&base.address, &quote.address))
+            else {
+                continue;
+            };
+            let jupiter = match self
+                .jupiter
+                .fetch_pair_price(&base.address, &quote.address)
+                .await
+            {
+                Ok(price) => price,
+                Err(e) => {
+                    warn!(
+                        "No Jupiter price for {}/{}: {}",
+                        base.symbol, quote.symbol, e
+                    );
+                    continue;
+                }
+            };
+
+            spreads.insert(
+                get_pair_ot_token_address_from_tokens(pair)?,
+                Spread::new(time, jupiter, raydium),
+            );
+        }
+
+        Ok(spreads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fetcher::tests::fixture_fetcher,
+        jup::prices::{TokenSymbol, JUP_API},
+        ray::RAYDIUM_BASE_API,
+    };
+
+    #[test]
+    fn test_spread_bps() {
+        let spread = Spread::new(0, 100.0, 101.0);
+        assert!((spread.bps - 99.502487).abs() < 1e-6);
+        assert!(Spread::new(0, 101.0, 100.0).bps < 0.0);
+        assert_eq!(Spread::new(0, 0.0, 0.0).bps, 0.0);
+    }
+
+    #[test]
+    fn test_events_fire_on_crossing_only() {
+        let mut monitor = DivergenceMonitor::new(DivergenceSettings {
+            threshold_bps: 50.0,
+            history: 3,
+        });
+        let record = |monitor: &mut DivergenceMonitor, raydium: f64| {
+            monitor.record("jlp_sol", "JLP/SOL", Spread::new(0, 100.0, raydium))
+        };
+
+        assert_eq!(record(&mut monitor, 100.2), None);
+        assert!(matches!(
+            record(&mut monitor, 101.0),
+            Some(DivergenceEvent::Diverged { .. })
+        ));
+        // Still apart, no repeat.
+        assert_eq!(record(&mut monitor, 99.0), None);
+        assert!(matches!(
+            record(&mut monitor, 100.1),
+            Some(DivergenceEvent::Converged { .. })
+        ));
+
+        assert_eq!(monitor.history("jlp_sol").count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_spreads_replayed() {
+        let venues = VenuePrices::new(
+            PriceFetcher::with_fetcher(fixture_fetcher(), JUP_API),
+            RaydiumFetcher::with_fetcher(fixture_fetcher(), RAYDIUM_BASE_API),
+        );
+        let token = |address: &str, symbol| Token {
+            address: address.to_owned(),
+            symbol,
+            ..Default::default()
+        };
+        let jlp = token(
+            "27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4",
+            TokenSymbol::JLP,
+        );
+        let sol = token(
+            "So11111111111111111111111111111111111111112",
+            TokenSymbol::SOL,
+        );
+
+        let spreads = venues.fetch_spreads(&[[jlp, sol]]).await.unwrap();
+
+        let spread = spreads
+            .get("27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4_So11111111111111111111111111111111111111112")
+            .unwrap();
+        assert!((spread.jupiter - 0.02497).abs() < 1e-12);
+        assert!((spread.raydium - 1.0 / 40.0556).abs() < 1e-9);
+        assert!(spread.bps < 0.0);
+    }
+}
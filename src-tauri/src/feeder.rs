@@ -1,6 +1,15 @@
+use std::collections::HashMap;
+
 use crate::{
-    ray::{fetch_pool_info_by_id, PoolId},
-    token_registry::Token,
+    change::PriceChange,
+    formatter::format_price,
+    jup::quote::QuoteInfo,
+    liquidation::LiquidationStatus,
+    ray::{PoolId, RaydiumFetcher},
+    source::PriceSourceKind,
+    ta::TaSnapshot,
+    time::get_unix_timestamp,
+    token_registry::{get_pair_ot_token_address_from_tokens, invert_pair_address, Token},
 };
 
 #[derive(Default, Debug, Clone)]
@@ -8,6 +17,37 @@ pub struct PriceInfo {
     pub price: Option<f64>,
     pub formatted_price: String,
     pub updated_at: u64,
+    // Which backend produced this price.
+    pub source: Option<PriceSourceKind>,
+    // Why the latest fetch for this key failed.
+    pub error: Option<String>,
+    // The price is the last good value, kept because the latest fetch failed.
+    pub is_stale: bool,
+    // Oracle confidence interval, in the same unit as the price.
+    pub confidence: Option<f64>,
+    // Set when the price comes from a swap quote rather than the mid price.
+    pub quote: Option<QuoteInfo>,
+    // Indicators over this key's price history.
+    pub ta: Option<TaSnapshot>,
+    // Raydium against Jupiter, set for pairs both venues trade.
+    pub spread_bps: Option<f64>,
+    // Moves over the last hour, day and since start.
+    pub change: Option<PriceChange>,
+}
+
+impl PriceInfo {
+    pub fn failed(error: impl ToString) -> Self {
+        Self {
+            formatted_price: "…".to_owned(),
+            updated_at: get_unix_timestamp(),
+            error: Some(error.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.price.is_some() && self.error.is_none()
+    }
 }
 
 #[derive(Default, Debug, Clone)]
@@ -23,29 +63,355 @@ pub struct PairPriceInfo {
     pub price_info: PriceInfo,
 }
 
+impl PairPriceInfo {
+    /// The same pair priced the other way, e.g. SOL/JLP from JLP/SOL.
+    /// Quotes and indicators describe the original direction so they are left out.
+    pub fn inverted(&self) -> Self {
+        let price_info = &self.price_info;
+        let price = price_info
+            .price
+            .filter(|price| *price != 0.0)
+            .map(|price| 1.0 / price);
+
+        Self {
+            token_a: self.token_b.clone(),
+            token_b: self.token_a.clone(),
+            price_info: PriceInfo {
+                price,
+                formatted_price: price
+                    .map(format_price)
+                    .unwrap_or(price_info.formatted_price.clone()),
+                confidence: price_info
+                    .confidence
+                    .zip(price_info.price)
+                    .map(|(confidence, price)| confidence / (price * price)),
+                quote: None,
+                ta: None,
+                change: price_info.change.as_ref().map(PriceChange::inverted),
+                spread_bps: price_info.spread_bps.map(|bps| -bps),
+                ..price_info.clone()
+            },
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct OraclePriceInfo {
+    // Oracle key of the token, e.g. `So11111111111111111111111111111111111111112_PYTH`
+    pub id: String,
+    pub token: Token,
+    pub price_info: PriceInfo,
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct PerpValueInfo {
-    // e.g. SOL_PERPS
+    // Perps key of the market, e.g. `So11111111111111111111111111111111111111112_PERPS`
     pub id: String,
     pub token: Token,
     // TODO: we need better name, e.g. ValueUsdInfo.
     pub pnl_after_fees_usd: PriceInfo,
+    // Position of the market closest to liquidation.
+    pub liquidation: Option<LiquidationStatus>,
 }
 
 #[derive(Debug, Clone)]
 pub enum TokenOrPairPriceInfo {
     Pair(PairPriceInfo),
     Token(TokenPriceInfo),
+    Oracle(OraclePriceInfo),
     Perp(PerpValueInfo),
 }
 
+impl TokenOrPairPriceInfo {
+    pub fn price_info(&self) -> &PriceInfo {
+        match self {
+            TokenOrPairPriceInfo::Pair(info) => &info.price_info,
+            TokenOrPairPriceInfo::Token(info) => &info.price_info,
+            TokenOrPairPriceInfo::Oracle(info) => &info.price_info,
+            TokenOrPairPriceInfo::Perp(info) => &info.pnl_after_fees_usd,
+        }
+    }
+
+    pub fn price_info_mut(&mut self) -> &mut PriceInfo {
+        match self {
+            TokenOrPairPriceInfo::Pair(info) => &mut info.price_info,
+            TokenOrPairPriceInfo::Token(info) => &mut info.price_info,
+            TokenOrPairPriceInfo::Oracle(info) => &mut info.price_info,
+            TokenOrPairPriceInfo::Perp(info) => &mut info.pnl_after_fees_usd,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.price_info().is_ok()
+    }
+}
+
 pub type TokenOrPairAddress = String;
 
-pub async fn get_price_by_token_id(pool_id: PoolId) -> anyhow::Result<f64> {
-    let pool_info = fetch_pool_info_by_id(pool_id).await?;
+/// Price of a key, pairs answer in either order, e.g. `SOL_JLP` from the `JLP_SOL` price.
+pub fn get_price_info(
+    prices: &HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    address: &str,
+) -> Option<TokenOrPairPriceInfo> {
+    if let Some(info) = prices.get(address) {
+        return Some(info.clone());
+    }
+
+    let inverted_address = invert_pair_address(address)?;
+    if let Some(info) = prices.get(&inverted_address) {
+        return match info {
+            TokenOrPairPriceInfo::Pair(info) => Some(TokenOrPairPriceInfo::Pair(info.inverted())),
+            _ => None,
+        };
+    }
+
+    // Any two priced tokens make a pair.
+    let (token_a, token_b) = address.split_once('_')?;
+    let token_of = |address| match prices.get(address)? {
+        TokenOrPairPriceInfo::Token(info) => Some(info.token.clone()),
+        _ => None,
+    };
+    synthesize_pair_price(prices, &[token_of(token_a)?, token_of(token_b)?])
+        .map(TokenOrPairPriceInfo::Pair)
+}
+
+/// Prices a pair from the USD prices of its tokens, no request needed.
+/// Stale or failed when either side is, `None` when neither token was asked for.
+pub fn synthesize_pair_price(
+    prices: &HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    pair: &[Token; 2],
+) -> Option<PairPriceInfo> {
+    let [token_a, token_b] = pair;
+    let usd_price_info = |token: &Token| match prices.get(&token.address) {
+        Some(TokenOrPairPriceInfo::Token(info)) => Some(&info.price_info),
+        _ => None,
+    };
+    let (price_info_a, price_info_b) = (usd_price_info(token_a), usd_price_info(token_b));
+    if price_info_a.is_none() && price_info_b.is_none() {
+        return None;
+    }
+
+    let price_info = match (price_info_a, price_info_b) {
+        (Some(price_info_a), Some(price_info_b)) => {
+            match (price_info_a.price, price_info_b.price) {
+                (Some(price_a), Some(price_b)) if price_b != 0.0 => {
+                    let price = price_a / price_b;
+                    PriceInfo {
+                        price: Some(price),
+                        formatted_price: format_price(price),
+                        updated_at: price_info_a.updated_at.min(price_info_b.updated_at),
+                        source: Some(PriceSourceKind::Synthetic),
+                        error: price_info_a.error.clone().or(price_info_b.error.clone()),
+                        is_stale: price_info_a.is_stale || price_info_b.is_stale,
+                        ..Default::default()
+                    }
+                }
+                _ => PriceInfo::failed(
+                    price_info_a
+                        .error
+                        .clone()
+                        .or(price_info_b.error.clone())
+                        .unwrap_or(format!(
+                            "No USD price for {}/{}",
+                            token_a.symbol, token_b.symbol
+                        )),
+                ),
+            }
+        }
+        _ => PriceInfo::failed(format!(
+            "No USD price for {}/{}",
+            token_a.symbol, token_b.symbol
+        )),
+    };
+
+    Some(PairPriceInfo {
+        token_a: token_a.clone(),
+        token_b: token_b.clone(),
+        price_info,
+    })
+}
+
+/// Prices every pair from the token prices already in the map.
+pub fn synthesize_pairs(
+    prices: &HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    pairs: &[[Token; 2]],
+) -> HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> {
+    pairs
+        .iter()
+        .filter_map(|pair| {
+            Some((
+                get_pair_ot_token_address_from_tokens(pair).ok()?,
+                TokenOrPairPriceInfo::Pair(synthesize_pair_price(prices, pair)?),
+            ))
+        })
+        .collect()
+}
+
+/// Remembers good values and puts the last one back, flagged as stale, for keys that failed.
+pub fn keep_last_good_prices(
+    prices: &mut HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    last_good_prices: &mut HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+) {
+    for (address, info) in prices.iter_mut() {
+        if info.is_ok() {
+            last_good_prices.insert(address.clone(), info.clone());
+            continue;
+        }
+
+        if let Some(last_good) = last_good_prices.get(address) {
+            let error = info.price_info().error.clone();
+            *info = last_good.clone();
+            info.price_info_mut().error = error;
+            info.price_info_mut().is_stale = true;
+        }
+    }
+}
+
+pub async fn get_price_by_token_id(
+    raydium_fetcher: &RaydiumFetcher,
+    pool_id: PoolId,
+) -> anyhow::Result<f64> {
+    let pool_info = raydium_fetcher.fetch_pool_info_by_id(pool_id).await?;
 
     // Get price from pool that match id
     let price = pool_info.price;
 
     Ok(price)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_price(price_info: PriceInfo) -> TokenOrPairPriceInfo {
+        TokenOrPairPriceInfo::Token(TokenPriceInfo {
+            token: Token::default(),
+            price_info,
+        })
+    }
+
+    #[test]
+    fn test_keep_last_good_prices() {
+        let mut last_good_prices = HashMap::new();
+        let mut prices = HashMap::from([
+            (
+                "sol".to_owned(),
+                token_price(PriceInfo {
+                    price: Some(200.0),
+                    ..Default::default()
+                }),
+            ),
+            ("jup".to_owned(), token_price(PriceInfo::failed("timeout"))),
+        ]);
+        keep_last_good_prices(&mut prices, &mut last_good_prices);
+
+        assert!(prices["sol"].is_ok());
+        assert_eq!(prices["jup"].price_info().price, None);
+        assert_eq!(last_good_prices.len(), 1);
+
+        let mut prices =
+            HashMap::from([("sol".to_owned(), token_price(PriceInfo::failed("delisted")))]);
+        keep_last_good_prices(&mut prices, &mut last_good_prices);
+
+        let sol = prices["sol"].price_info();
+        assert_eq!(sol.price, Some(200.0));
+        assert!(sol.is_stale);
+        assert_eq!(sol.error.as_deref(), Some("delisted"));
+        assert!(!last_good_prices["sol"].price_info().is_stale);
+    }
+
+    #[test]
+    fn test_get_price_info_either_order() {
+        let token = |address: &str| Token {
+            address: address.to_owned(),
+            ..Default::default()
+        };
+        let prices = HashMap::from([(
+            "jlp_sol".to_owned(),
+            TokenOrPairPriceInfo::Pair(PairPriceInfo {
+                token_a: token("jlp"),
+                token_b: token("sol"),
+                price_info: PriceInfo {
+                    price: Some(0.025),
+                    spread_bps: Some(12.0),
+                    is_stale: true,
+                    ..Default::default()
+                },
+            }),
+        )]);
+
+        let Some(TokenOrPairPriceInfo::Pair(inverted)) = get_price_info(&prices, "sol_jlp") else {
+            panic!("Missing inverted pair");
+        };
+        assert_eq!(inverted.token_a.address, "sol");
+        assert_eq!(inverted.price_info.price, Some(40.0));
+        assert_eq!(inverted.price_info.formatted_price, "40");
+        assert_eq!(inverted.price_info.spread_bps, Some(-12.0));
+        assert!(inverted.price_info.is_stale);
+
+        assert!(get_price_info(&prices, "jlp_sol").is_some());
+        assert!(get_price_info(&prices, "jup_sol").is_none());
+    }
+
+    #[test]
+    fn test_synthesize_pairs() {
+        let token = |address: &str, price_info: PriceInfo| {
+            (
+                address.to_owned(),
+                TokenOrPairPriceInfo::Token(TokenPriceInfo {
+                    token: Token {
+                        address: address.to_owned(),
+                        ..Default::default()
+                    },
+                    price_info,
+                }),
+            )
+        };
+        let prices = HashMap::from([
+            token(
+                "jup",
+                PriceInfo {
+                    price: Some(0.8),
+                    updated_at: 100,
+                    ..Default::default()
+                },
+            ),
+            token(
+                "jlp",
+                PriceInfo {
+                    price: Some(4.0),
+                    updated_at: 90,
+                    is_stale: true,
+                    ..Default::default()
+                },
+            ),
+            token("sol", PriceInfo::failed("timeout")),
+        ]);
+        let pair = |a: &str, b: &str| {
+            [a, b].map(|address| Token {
+                address: address.to_owned(),
+                ..Default::default()
+            })
+        };
+
+        let synthetic = synthesize_pairs(
+            &prices,
+            &[pair("jup", "jlp"), pair("jup", "sol"), pair("usdt", "usdc")],
+        );
+
+        let jup_jlp = synthetic["jup_jlp"].price_info();
+        assert_eq!(jup_jlp.price, Some(0.2));
+        assert_eq!(jup_jlp.source, Some(PriceSourceKind::Synthetic));
+        assert_eq!(jup_jlp.updated_at, 90);
+        assert!(jup_jlp.is_stale);
+        assert_eq!(
+            synthetic["jup_sol"].price_info().error.as_deref(),
+            Some("timeout")
+        );
+        assert!(!synthetic.contains_key("usdt_usdc"));
+
+        // Unlisted pairs are priced on demand too.
+        let jlp_jup = get_price_info(&prices, "jlp_jup").unwrap();
+        assert_eq!(jlp_jup.price_info().price, Some(5.0));
+    }
+}
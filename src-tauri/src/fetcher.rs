@@ -1,19 +1,108 @@
-use anyhow::{anyhow, Result};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
 use log::warn;
-use reqwest;
-use serde::de::DeserializeOwned;
-use tokio::time::{timeout, Duration};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, RETRY_AFTER},
+    Client, Proxy, StatusCode,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+use tokio::time::{timeout, Duration, Instant};
+
+use crate::{
+    circuit_breaker::{endpoint_of, CircuitBreakerSettings, CircuitBreakers, EndpointHealth},
+    config::ApiConfig,
+};
 
 /// Helper function to calculate exponential backoff delay.
 fn exponential_backoff(retries: u32, base_backoff: Duration) -> Duration {
     base_backoff * 2u32.pow(retries)
 }
 
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("Request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Rate limited, retry after {retry_after:?}")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Unexpected status {status}: {body}")]
+    Status { status: StatusCode, body: String },
+    #[error("Failed to decode response: {0}")]
+    Decode(String),
+    #[error("Transport error: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("{endpoint} is unavailable, retry in {retry_in:?}")]
+    CircuitOpen {
+        endpoint: String,
+        retry_in: Duration,
+    },
+}
+
+impl FetchError {
+    /// Network or upstream trouble that may go away on its own, as opposed to a bad request.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            FetchError::Timeout(_)
+            | FetchError::RateLimited { .. }
+            | FetchError::Decode(_)
+            | FetchError::Transport(_)
+            | FetchError::CircuitOpen { .. } => true,
+            FetchError::Status { status, .. } => status.is_server_error(),
+            // JSON-RPC reserves this range for server side trouble, e.g. a node behind.
+            FetchError::Rpc { code, .. } => (-32099..=-32000).contains(code),
+            FetchError::NotFound(_) => false,
+        }
+    }
+}
+
+/// Reads `Retry-After` as either delay seconds or an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let retry_at = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    Some((retry_at - Utc::now()).to_std().unwrap_or_default())
+}
+
+async fn read_body(response: reqwest::Response) -> Result<Vec<u8>, FetchError> {
+    let status = response.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(FetchError::RateLimited {
+            retry_after: parse_retry_after(response.headers()),
+        });
+    }
+
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(FetchError::Status {
+            status,
+            body: body.chars().take(200).collect(),
+        });
+    }
+
+    Ok(response.bytes().await?.to_vec())
+}
+
 #[derive(Debug, Clone)]
 pub struct RetrySettings {
     pub max_retries: usize,
     pub request_timeout: Duration,
     pub base_backoff: Duration,
+    // Longest `Retry-After` we are willing to wait before giving up.
+    pub max_retry_after: Duration,
 }
 
 impl Default for RetrySettings {
@@ -22,6 +111,7 @@ impl Default for RetrySettings {
             max_retries: 3,
             request_timeout: Duration::from_secs(10),
             base_backoff: Duration::from_secs(2),
+            max_retry_after: Duration::from_secs(30),
         }
     }
 }
@@ -45,10 +135,170 @@ impl RetrySettings {
         self.base_backoff = backoff;
         self
     }
+
+    pub fn with_max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Decides per error class whether to retry and how long to wait first.
+    fn retry_delay(&self, error: &FetchError, retries: usize) -> Option<Duration> {
+        if !error.is_transient() || retries >= self.max_retries {
+            return None;
+        }
+
+        match error {
+            // Waiting out the cool down here would stall every caller.
+            FetchError::CircuitOpen { .. } => None,
+            FetchError::RateLimited {
+                retry_after: Some(retry_after),
+            } => (*retry_after <= self.max_retry_after).then_some(*retry_after),
+            _ => Some(exponential_backoff(retries as u32, self.base_backoff)),
+        }
+    }
 }
 
+/// Builds the one HTTP client every fetcher shares.
+pub fn build_client(config: &ApiConfig) -> anyhow::Result<Client> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in &config.headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).context("Invalid header name")?,
+            HeaderValue::from_str(value).context("Invalid header value")?,
+        );
+    }
+
+    if let Some(api_key) = &config.api_key {
+        let mut value = HeaderValue::from_str(api_key).context("Invalid api key")?;
+        value.set_sensitive(true);
+        headers.insert(HeaderName::from_static("x-api-key"), value);
+    }
+
+    let mut builder = Client::builder()
+        .user_agent(config.user_agent.as_str())
+        .default_headers(headers);
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(Proxy::all(proxy).context("Invalid proxy")?);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Where response bodies come from, see `Fetcher::with_fixtures`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum FixtureMode {
+    #[default]
+    Live,
+    // Hits the network and saves every successful body under the directory.
+    Record(PathBuf),
+    // Serves saved bodies only, never touches the network.
+    Replay(PathBuf),
+}
+
+impl FixtureMode {
+    /// `CATBOT_FIXTURES=record` re-records, `live` bypasses fixtures, anything else replays.
+    pub fn from_env(dir: impl Into<PathBuf>) -> Self {
+        match std::env::var("CATBOT_FIXTURES").as_deref() {
+            Ok("record") => FixtureMode::Record(dir.into()),
+            Ok("live") => FixtureMode::Live,
+            _ => FixtureMode::Replay(dir.into()),
+        }
+    }
+}
+
+/// Stable fixture file name for a request, e.g. `api.jup.ag-1f0c9a7d2b3e4f51.json`.
+pub fn fixture_path(dir: &Path, url: &str, body: Option<&str>) -> PathBuf {
+    // FNV-1a, stable across builds unlike `DefaultHasher`.
+    let bytes = match body {
+        Some(body) => format!("{url}\n{body}").into_bytes(),
+        None => url.as_bytes().to_vec(),
+    };
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    dir.join(format!(
+        "{}-{:016x}.json",
+        endpoint_of(url).replace(':', "_"),
+        hash
+    ))
+}
+
+fn read_fixture(dir: &Path, url: &str, body: Option<&str>) -> Result<Arc<Vec<u8>>, FetchError> {
+    let path = fixture_path(dir, url, body);
+    std::fs::read(&path).map(Arc::new).map_err(|_| {
+        FetchError::NotFound(format!(
+            "Fixture {} for {url}, record it with CATBOT_FIXTURES=record",
+            path.display()
+        ))
+    })
+}
+
+fn write_fixture(
+    dir: &Path,
+    url: &str,
+    body: Option<&str>,
+    response: &[u8],
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(fixture_path(dir, url, body), response)
+}
+
+struct CacheEntry {
+    body: Arc<Vec<u8>>,
+    expires_at: Instant,
+}
+
+/// Short lived response bodies keyed by URL.
+#[derive(Default)]
+struct ResponseCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    // One lock per URL so concurrent identical requests wait for a single call.
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl ResponseCache {
+    fn get(&self, url: &str) -> Option<Arc<Vec<u8>>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(url)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.body.clone())
+    }
+
+    fn insert(&self, url: &str, body: Arc<Vec<u8>>, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|_, entry| entry.expires_at > now);
+        entries.insert(
+            url.to_owned(),
+            CacheEntry {
+                body,
+                expires_at: now + ttl,
+            },
+        );
+    }
+
+    fn remove(&self, url: &str) {
+        self.entries.lock().unwrap().remove(url);
+    }
+
+    fn lock_for(&self, url: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        in_flight.retain(|_, lock| Arc::strong_count(lock) > 1);
+        in_flight.entry(url.to_owned()).or_default().clone()
+    }
+}
+
+/// Cheap to clone, clones share the same underlying client, response cache and breakers.
+#[derive(Clone)]
 pub struct Fetcher {
+    client: Client,
     settings: RetrySettings,
+    // Endpoint prefix and how long its responses stay fresh.
+    cache_ttls: Vec<(String, Duration)>,
+    cache: Arc<ResponseCache>,
+    breakers: Arc<CircuitBreakers>,
+    fixtures: FixtureMode,
 }
 
 impl Default for Fetcher {
@@ -59,40 +309,483 @@ impl Default for Fetcher {
 
 impl Fetcher {
     pub fn new() -> Self {
+        Self::with_settings(RetrySettings::default())
+    }
+
+    pub fn with_settings(settings: RetrySettings) -> Self {
         Self {
-            settings: RetrySettings::default(),
+            client: Client::new(),
+            settings,
+            cache_ttls: vec![],
+            cache: Default::default(),
+            breakers: Default::default(),
+            fixtures: FixtureMode::Live,
         }
     }
 
-    pub fn with_settings(settings: RetrySettings) -> Self {
-        Self { settings }
+    pub fn from_config(config: &ApiConfig) -> anyhow::Result<Self> {
+        let cache = &config.cache;
+        Ok(Self {
+            client: build_client(config)?,
+            ..Self::new()
+        }
+        .with_circuit_breaker(config.circuit_breaker.clone())
+        .with_cache_ttl(&config.jup_price_api, cache.jup_price_ttl())
+        .with_cache_ttl(&config.jup_perps_api, cache.jup_perps_ttl())
+        .with_cache_ttl(&config.raydium_api, cache.raydium_ttl()))
     }
 
-    pub async fn fetch_with_retry<F, R, T>(&self, url: &str, processor: F) -> Result<T>
+    /// Caches successful responses for URLs starting with `prefix`, zero disables it.
+    pub fn with_cache_ttl(mut self, prefix: &str, ttl: Duration) -> Self {
+        self.cache_ttls.retain(|(other, _)| other != prefix);
+        if !ttl.is_zero() {
+            self.cache_ttls.push((prefix.to_owned(), ttl));
+        }
+        // Longest prefix first so the most specific endpoint wins.
+        self.cache_ttls
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, settings: CircuitBreakerSettings) -> Self {
+        self.breakers = Arc::new(CircuitBreakers::new(settings));
+        self
+    }
+
+    /// Records responses to, or replays them from, fixture files.
+    pub fn with_fixtures(mut self, fixtures: FixtureMode) -> Self {
+        self.fixtures = fixtures;
+        self
+    }
+
+    /// Breaker state and counters of every upstream host seen so far.
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.breakers.health()
+    }
+
+    fn cache_ttl(&self, url: &str) -> Option<Duration> {
+        self.cache_ttls
+            .iter()
+            .find(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .map(|(_, ttl)| *ttl)
+    }
+
+    /// GET `url`, or POST `body` as JSON when given.
+    async fn fetch_body(&self, url: &str, body: Option<&str>) -> Result<Arc<Vec<u8>>, FetchError> {
+        if let FixtureMode::Replay(dir) = &self.fixtures {
+            return read_fixture(dir, url, body);
+        }
+
+        let endpoint = endpoint_of(url);
+        if let Err(retry_in) = self.breakers.check(&endpoint) {
+            return Err(FetchError::CircuitOpen { endpoint, retry_in });
+        }
+
+        let request = match body {
+            Some(body) => self
+                .client
+                .post(url)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_owned()),
+            None => self.client.get(url),
+        }
+        .send();
+        let result = match timeout(self.settings.request_timeout, request).await {
+            Ok(Ok(response)) => read_body(response).await.map(Arc::new),
+            Ok(Err(e)) if e.is_timeout() => Err(FetchError::Timeout(self.settings.request_timeout)),
+            Ok(Err(e)) => Err(FetchError::Transport(e)),
+            Err(_) => Err(FetchError::Timeout(self.settings.request_timeout)),
+        };
+
+        // A client error still means the upstream answered.
+        match &result {
+            Err(e) if e.is_transient() => self.breakers.record_failure(&endpoint, &e.to_string()),
+            _ => self.breakers.record_success(&endpoint),
+        }
+
+        if let (FixtureMode::Record(dir), Ok(response)) = (&self.fixtures, &result) {
+            if let Err(e) = write_fixture(dir, url, body, response) {
+                warn!("Failed to record fixture for {}: {}", url, e);
+            }
+        }
+
+        result
+    }
+
+    pub async fn fetch_with_retry<F, R, T>(&self, url: &str, processor: F) -> Result<T, FetchError>
     where
         R: DeserializeOwned,
-        F: Fn(R) -> Result<T>,
+        F: Fn(R) -> Result<T, FetchError>,
     {
+        self.request_with_retry(url, None, processor).await
+    }
+
+    /// Same as `fetch_with_retry` but POSTs `body`, e.g. for JSON-RPC. Never cached.
+    pub async fn post_with_retry<B, F, R, T>(
+        &self,
+        url: &str,
+        body: &B,
+        processor: F,
+    ) -> Result<T, FetchError>
+    where
+        B: Serialize,
+        R: DeserializeOwned,
+        F: Fn(R) -> Result<T, FetchError>,
+    {
+        let body = serde_json::to_string(body).map_err(|e| FetchError::Decode(e.to_string()))?;
+        self.request_with_retry(url, Some(&body), processor).await
+    }
+
+    async fn request_with_retry<F, R, T>(
+        &self,
+        url: &str,
+        request_body: Option<&str>,
+        processor: F,
+    ) -> Result<T, FetchError>
+    where
+        R: DeserializeOwned,
+        F: Fn(R) -> Result<T, FetchError>,
+    {
+        let ttl = match request_body {
+            Some(_) => None,
+            None => self.cache_ttl(url),
+        };
+
+        // Identical cacheable requests queue here and reuse the first one's response.
+        let lock = ttl.map(|_| self.cache.lock_for(url));
+        let _guard = match &lock {
+            Some(lock) => Some(lock.lock().await),
+            None => None,
+        };
+
         let mut retries = 0;
 
         loop {
-            match timeout(self.settings.request_timeout, reqwest::get(url)).await {
-                Ok(response) => {
-                    let response = response?;
-                    let api_response = response.json::<R>().await?;
-                    return processor(api_response);
-                }
-                Err(e) => {
-                    retries += 1;
-                    if retries >= self.settings.max_retries {
-                        return Err(anyhow!("Request failed after {} retries: {}", retries, e));
+            let cached = ttl.and_then(|_| self.cache.get(url));
+            let body = match &cached {
+                Some(body) => Ok(body.clone()),
+                None => self.fetch_body(url, request_body).await,
+            };
+            let result = body.and_then(|body| {
+                let response = serde_json::from_slice::<R>(&body)
+                    .map_err(|e| FetchError::Decode(e.to_string()))?;
+                processor(response).map(|value| (body, value))
+            });
+
+            let error = match result {
+                Ok((body, value)) => {
+                    if let (Some(ttl), None) = (ttl, &cached) {
+                        self.cache.insert(url, body, ttl);
                     }
-                    warn!("Request failed (attempt {}): {}", retries, e);
+                    return Ok(value);
+                }
+                Err(error) => error,
+            };
+
+            if cached.is_some() {
+                self.cache.remove(url);
+            }
 
-                    let delay = exponential_backoff(retries as u32, self.settings.base_backoff);
+            retries += 1;
+            match self.settings.retry_delay(&error, retries) {
+                Some(delay) => {
+                    warn!("Request failed (attempt {}): {}", retries, error);
                     tokio::time::sleep(delay).await;
                 }
+                None => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves the given raw HTTP responses, one per connection.
+    pub(crate) async fn serve(responses: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        format!("http://{address}")
+    }
+
+    /// Raw `200 OK` response carrying `body`.
+    pub(crate) fn ok_response(body: &str) -> &'static str {
+        format!(
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .leak()
+    }
+
+    /// Replays `tests/fixtures`, or records them with `CATBOT_FIXTURES=record`.
+    pub(crate) fn fixture_fetcher() -> Fetcher {
+        Fetcher::new().with_fixtures(FixtureMode::from_env(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures"
+        )))
+    }
+
+    fn fast_fetcher() -> Fetcher {
+        Fetcher::with_settings(
+            RetrySettings::new()
+                .with_base_backoff(Duration::from_millis(1))
+                .with_request_timeout(Duration::from_secs(2)),
+        )
+    }
+
+    /// Answers every connection with `body` after `delay` and counts the requests.
+    async fn serve_counting(body: &'static str, delay: Duration) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = socket.read(&mut buf).await;
+                    tokio::time::sleep(delay).await;
+                    socket
+                        .write_all(ok_response(body).as_bytes())
+                        .await
+                        .unwrap();
+                });
             }
+        });
+
+        (format!("http://{address}"), count)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_identical_requests_share_one_call() {
+        let (url, count) = serve_counting(r#"{"ok":true}"#, Duration::from_millis(100)).await;
+        let fetcher = fast_fetcher().with_cache_ttl(&url, Duration::from_millis(300));
+
+        let fetch = || fetcher.fetch_with_retry(&url, |value: Value| Ok(value["ok"].as_bool()));
+        let (a, b, c) = tokio::join!(fetch(), fetch(), fetch());
+        assert_eq!(
+            (a.unwrap(), b.unwrap(), c.unwrap()),
+            (Some(true), Some(true), Some(true))
+        );
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // Clones share the cache.
+        let cloned = fetcher.clone();
+        cloned
+            .fetch_with_retry(&url, |value: Value| Ok(value))
+            .await
+            .unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        fetch().await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_no_cache_without_ttl() {
+        let (url, count) = serve_counting(r#"{"ok":true}"#, Duration::ZERO).await;
+        let fetcher = fast_fetcher().with_cache_ttl("http://other", Duration::from_secs(5));
+
+        for _ in 0..2 {
+            fetcher
+                .fetch_with_retry(&url, |value: Value| Ok(value))
+                .await
+                .unwrap();
         }
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_build_client_from_config() {
+        let config = ApiConfig {
+            api_key: Some("secret".to_owned()),
+            proxy: Some("http://127.0.0.1:3128".to_owned()),
+            headers: [("x-team".to_owned(), "catbot".to_owned())].into(),
+            ..Default::default()
+        };
+        assert!(build_client(&config).is_ok());
+
+        let config = ApiConfig {
+            headers: [("bad header".to_owned(), "x".to_owned())].into(),
+            ..Default::default()
+        };
+        assert!(build_client(&config).is_err());
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(7)));
+
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(parse_retry_after(&headers), Some(Duration::ZERO));
+
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_retry_policy_per_class() {
+        let settings = RetrySettings::new();
+        let bad_request = FetchError::Status {
+            status: StatusCode::BAD_REQUEST,
+            body: String::new(),
+        };
+        let server_error = FetchError::Status {
+            status: StatusCode::BAD_GATEWAY,
+            body: String::new(),
+        };
+        let rate_limited = FetchError::RateLimited {
+            retry_after: Some(Duration::from_secs(5)),
+        };
+        let too_long = FetchError::RateLimited {
+            retry_after: Some(Duration::from_secs(600)),
+        };
+
+        assert_eq!(settings.retry_delay(&bad_request, 1), None);
+        assert_eq!(
+            settings.retry_delay(&FetchError::NotFound("x".into()), 1),
+            None
+        );
+        assert_eq!(
+            settings.retry_delay(&server_error, 1),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(settings.retry_delay(&server_error, 3), None);
+        assert_eq!(
+            settings.retry_delay(&rate_limited, 1),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(settings.retry_delay(&too_long, 1), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_rate_limit_and_server_error() {
+        let url = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            ok_response(r#"{"ok":true}"#),
+        ])
+        .await;
+
+        let fetcher = Fetcher::with_settings(
+            RetrySettings::new()
+                .with_max_retries(5)
+                .with_base_backoff(Duration::from_millis(1)),
+        );
+        let value = fetcher
+            .fetch_with_retry(&url, |value: Value| Ok(value["ok"].as_bool()))
+            .await
+            .unwrap();
+
+        assert_eq!(value, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_malformed_body() {
+        let url = serve(vec![
+            "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 5\r\n\r\n{\"ok\"",
+            ok_response(r#"{"ok":true}"#),
+        ])
+        .await;
+
+        let value = fast_fetcher()
+            .fetch_with_retry(&url, |value: Value| Ok(value["ok"].as_bool()))
+            .await
+            .unwrap();
+
+        assert_eq!(value, Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_on_client_error() {
+        let url = serve(vec![
+            "HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 11\r\n\r\nbad request",
+        ])
+        .await;
+
+        let error = fast_fetcher()
+            .fetch_with_retry(&url, |value: Value| Ok(value))
+            .await
+            .unwrap_err();
+
+        assert!(!error.is_transient());
+        assert!(matches!(error, FetchError::Status { status, .. } if status == 400));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_and_skips_the_network() {
+        let url = serve(vec![
+            "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+        ])
+        .await;
+
+        let fetcher = fast_fetcher().with_circuit_breaker(CircuitBreakerSettings {
+            failure_threshold: 2,
+            open_secs: 60,
+        });
+        let error = fetcher
+            .fetch_with_retry(&url, |value: Value| Ok(value))
+            .await
+            .unwrap_err();
+
+        // The second attempt opened the circuit, the third never left the process.
+        assert!(matches!(error, FetchError::CircuitOpen { .. }));
+        let health = fetcher.health();
+        assert_eq!(health.len(), 1);
+        assert!(health[0].is_degraded());
+        assert_eq!(health[0].total_failures, 2);
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let url = serve(vec![ok_response(r#"{"ok":true}"#)]).await;
+        let dir = std::env::temp_dir().join(format!("catbot-fixtures-{}", std::process::id()));
+
+        let recorded = fast_fetcher()
+            .with_fixtures(FixtureMode::Record(dir.clone()))
+            .fetch_with_retry(&url, |value: Value| Ok(value))
+            .await
+            .unwrap();
+
+        // The server is gone by now, only the fixture can answer.
+        let replayer = fast_fetcher().with_fixtures(FixtureMode::Replay(dir.clone()));
+        let replayed = replayer
+            .fetch_with_retry(&url, |value: Value| Ok(value))
+            .await
+            .unwrap();
+        assert_eq!(recorded, replayed);
+
+        let missing = replayer
+            .fetch_with_retry(&format!("{url}/missing"), |value: Value| Ok(value))
+            .await
+            .unwrap_err();
+        assert!(matches!(missing, FetchError::NotFound(_)));
+        assert!(replayer.health().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
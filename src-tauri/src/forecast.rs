@@ -0,0 +1,338 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    candle::{Candle, CandleSeries, Interval},
+    jup::charts::CandleFetcher,
+    store::{PriceStore, Tick},
+    time::get_unix_timestamp,
+    token_registry::Token,
+};
+
+// Hourly bars, a day ahead, fitted on the last 30 days.
+pub const FORECAST_INTERVAL: Interval = Interval::OneHour;
+pub const FORECAST_HORIZON_SECS: u64 = 24 * 60 * 60;
+const HISTORY_SECS: u64 = 30 * 24 * 60 * 60;
+
+// Walk-forward trains on at least a week and steps a day at a time.
+const MIN_TRAIN_BARS: usize = 7 * 24;
+const WALK_FORWARD_STRIDE: usize = 24;
+
+// Two sided 95% band.
+const Z_95: f64 = 1.96;
+
+const ALPHAS: [f64; 9] = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+const BETAS: [f64; 5] = [0.0, 0.05, 0.1, 0.2, 0.3];
+
+/// Holt's linear exponential smoothing, a level and a trend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HoltModel {
+    pub alpha: f64,
+    pub beta: f64,
+    pub level: f64,
+    pub trend: f64,
+    // Standard deviation of the one step ahead errors.
+    pub sigma: f64,
+}
+
+impl HoltModel {
+    /// Fits with the given smoothing, `None` under 3 values.
+    pub fn fit_with(values: &[f64], alpha: f64, beta: f64) -> Option<Self> {
+        if values.len() < 3 {
+            return None;
+        }
+
+        let mut level = values[1];
+        let mut trend = values[1] - values[0];
+        let mut squared_errors = 0.0;
+        for value in &values[2..] {
+            let error = value - (level + trend);
+            squared_errors += error * error;
+
+            let previous_level = level;
+            level = alpha * value + (1.0 - alpha) * (level + trend);
+            trend = beta * (level - previous_level) + (1.0 - beta) * trend;
+        }
+
+        Some(Self {
+            alpha,
+            beta,
+            level,
+            trend,
+            sigma: (squared_errors / (values.len() - 2) as f64).sqrt(),
+        })
+    }
+
+    /// Fits the smoothing with the smallest one step ahead error on a small grid.
+    pub fn fit(values: &[f64]) -> Option<Self> {
+        ALPHAS
+            .iter()
+            .flat_map(|alpha| BETAS.iter().map(move |beta| (*alpha, *beta)))
+            .filter_map(|(alpha, beta)| Self::fit_with(values, alpha, beta))
+            .min_by(|a, b| a.sigma.total_cmp(&b.sigma))
+    }
+
+    /// Point forecast `steps` ahead.
+    pub fn predict(&self, steps: usize) -> f64 {
+        self.level + steps as f64 * self.trend
+    }
+
+    /// Forecast standard deviation `steps` ahead, it grows with the smoothing weights.
+    pub fn std_dev(&self, steps: usize) -> f64 {
+        let variance_factor = 1.0
+            + (1..steps)
+                .map(|j| (self.alpha * (1.0 + j as f64 * self.beta)).powi(2))
+                .sum::<f64>();
+        self.sigma * variance_factor.sqrt()
+    }
+}
+
+/// A point forecast with its 95% band.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Forecast {
+    // When the forecast is for, unix seconds.
+    pub time: u64,
+    pub price: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Forecasts the close `horizon_secs` after the last candle.
+pub fn forecast(candles: &[Candle], interval: Interval, horizon_secs: u64) -> Option<Forecast> {
+    let last = candles.last()?;
+    let steps = (horizon_secs / interval.secs()).max(1) as usize;
+    let closes = candles.iter().map(|c| c.close).collect::<Vec<_>>();
+    let model = HoltModel::fit(&closes)?;
+
+    let price = model.predict(steps);
+    let width = Z_95 * model.std_dev(steps);
+    Some(Forecast {
+        time: last.time + steps as u64 * interval.secs(),
+        price,
+        lower: price - width,
+        upper: price + width,
+    })
+}
+
+/// How past forecasts did against what happened.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccuracyReport {
+    pub samples: usize,
+    pub mae: f64,
+    pub rmse: f64,
+    // e.g. 1.5 for 1.5%
+    pub mape: f64,
+    // Share of actual prices inside the 95% band.
+    pub coverage: f64,
+    // Error of assuming the price stays put.
+    pub naive_mae: f64,
+}
+
+impl AccuracyReport {
+    /// Above zero when the model beats assuming no change.
+    pub fn skill(&self) -> f64 {
+        if self.naive_mae == 0.0 {
+            return 0.0;
+        }
+        1.0 - self.mae / self.naive_mae
+    }
+
+    /// Enough samples, better than no change and a band that means something.
+    pub fn is_trustworthy(&self) -> bool {
+        self.samples >= 10 && self.skill() > 0.0 && self.coverage >= 0.8
+    }
+}
+
+/// Refits at every `stride` origin after `min_train` values and checks the forecast `steps` ahead.
+pub fn walk_forward(
+    values: &[f64],
+    steps: usize,
+    min_train: usize,
+    stride: usize,
+) -> AccuracyReport {
+    let mut report = AccuracyReport::default();
+    let (mut abs_error, mut squared_error, mut pct_error, mut covered, mut naive_error) =
+        (0.0, 0.0, 0.0, 0, 0.0);
+
+    let mut origin = min_train.max(3);
+    while origin + steps <= values.len() {
+        let train = &values[..origin];
+        let actual = values[origin + steps - 1];
+        if let Some(model) = HoltModel::fit(train) {
+            let predicted = model.predict(steps);
+            let error = actual - predicted;
+
+            report.samples += 1;
+            abs_error += error.abs();
+            squared_error += error * error;
+            if actual != 0.0 {
+                pct_error += (error / actual).abs();
+            }
+            if error.abs() <= Z_95 * model.std_dev(steps) {
+                covered += 1;
+            }
+            naive_error += (actual - train[train.len() - 1]).abs();
+        }
+        origin += stride.max(1);
+    }
+
+    if report.samples > 0 {
+        let n = report.samples as f64;
+        report.mae = abs_error / n;
+        report.rmse = (squared_error / n).sqrt();
+        report.mape = pct_error / n * 100.0;
+        report.coverage = covered as f64 / n;
+        report.naive_mae = naive_error / n;
+    }
+    report
+}
+
+/// Chart candles with gaps filled by bars built from recorded ticks.
+pub fn merge_ticks(candles: &[Candle], ticks: &[Tick], interval: Interval) -> Vec<Candle> {
+    let mut series = CandleSeries::new(interval, ticks.len().max(1));
+    for tick in ticks {
+        series.update(tick.time, tick.price);
+    }
+
+    let mut merged: BTreeMap<u64, Candle> = series
+        .candles()
+        .into_iter()
+        .map(|candle| (candle.time, candle))
+        .collect();
+    // The chart API sees every trade, prefer it where both have a bar.
+    merged.extend(candles.iter().map(|candle| (candle.time, *candle)));
+    merged.into_values().collect()
+}
+
+/// Day ahead forecast of a key and whether to trust it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForecastReport {
+    pub key: String,
+    pub forecast: Option<Forecast>,
+    pub accuracy: AccuracyReport,
+}
+
+/// Forecasts a token, or a `[base, quote]` pair, from charts and the local store.
+pub async fn forecast_tokens(
+    candle_fetcher: &CandleFetcher,
+    price_store: Option<&PriceStore>,
+    key: &str,
+    tokens: &[Token],
+) -> Result<ForecastReport> {
+    let (base, quote) = match tokens {
+        [base] => (base, None),
+        [base, quote] => (base, Some(quote)),
+        _ => bail!("Nothing to forecast for {}", key),
+    };
+
+    let time_to = get_unix_timestamp();
+    let time_from = time_to - HISTORY_SECS;
+    let candles = candle_fetcher
+        .fetch_candles(base, quote, FORECAST_INTERVAL, time_from, time_to)
+        .await?;
+    let ticks = match price_store {
+        Some(price_store) => price_store.query(key, time_from, time_to)?,
+        None => vec![],
+    };
+    let candles = merge_ticks(&candles, &ticks, FORECAST_INTERVAL);
+
+    let closes = candles.iter().map(|c| c.close).collect::<Vec<_>>();
+    let steps = (FORECAST_HORIZON_SECS / FORECAST_INTERVAL.secs()) as usize;
+    Ok(ForecastReport {
+        key: key.to_owned(),
+        forecast: forecast(&candles, FORECAST_INTERVAL, FORECAST_HORIZON_SECS),
+        accuracy: walk_forward(&closes, steps, MIN_TRAIN_BARS, WALK_FORWARD_STRIDE),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise in `[-1, 1)`.
+    fn noise(len: usize) -> Vec<f64> {
+        let mut state = 42u64;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    fn hourly(closes: &[f64]) -> Vec<Candle> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, close)| Candle::flat(i as u64 * 3_600, *close))
+            .collect()
+    }
+
+    #[test]
+    fn test_forecast_follows_a_trend() {
+        let closes = (0..100).map(|i| 100.0 + 0.5 * i as f64).collect::<Vec<_>>();
+
+        let forecast = forecast(&hourly(&closes), Interval::OneHour, 24 * 3_600).unwrap();
+
+        assert_eq!(forecast.time, 99 * 3_600 + 24 * 3_600);
+        assert!((forecast.price - (100.0 + 0.5 * 123.0)).abs() < 1e-6);
+        assert!(forecast.upper - forecast.lower < 1e-6);
+    }
+
+    #[test]
+    fn test_band_widens_with_horizon() {
+        let closes = noise(200).iter().map(|n| 100.0 + n).collect::<Vec<_>>();
+        let model = HoltModel::fit(&closes).unwrap();
+
+        assert!(model.sigma > 0.0);
+        assert!(model.std_dev(24) > model.std_dev(1));
+        assert_eq!(model.std_dev(1), model.sigma);
+    }
+
+    #[test]
+    fn test_walk_forward_report() {
+        // Trend plus noise, the model should beat assuming no change.
+        let closes = noise(24 * 30)
+            .iter()
+            .enumerate()
+            .map(|(i, n)| 100.0 + 0.1 * i as f64 + n)
+            .collect::<Vec<_>>();
+
+        let report = walk_forward(&closes, 24, MIN_TRAIN_BARS, WALK_FORWARD_STRIDE);
+
+        assert_eq!(
+            report.samples,
+            (closes.len() - MIN_TRAIN_BARS - 24) / 24 + 1
+        );
+        assert!(report.mae > 0.0 && report.rmse >= report.mae);
+        assert!(report.skill() > 0.5, "{report:?}");
+        assert!(report.coverage >= 0.8, "{report:?}");
+        assert!(report.is_trustworthy());
+
+        // Too short to say anything.
+        assert!(!walk_forward(&closes[..100], 24, MIN_TRAIN_BARS, 24).is_trustworthy());
+    }
+
+    #[test]
+    fn test_merge_ticks_fills_gaps_in_charts() {
+        let candles = hourly(&[1.0, 2.0]);
+        let tick = |time: u64, price: f64| Tick {
+            key: "sol".to_owned(),
+            time,
+            price,
+            source: None,
+        };
+        let ticks = [tick(3_700, 9.0), tick(7_300, 3.0), tick(7_400, 4.0)];
+
+        let merged = merge_ticks(&candles, &ticks, Interval::OneHour);
+
+        // The chart wins the hour both have, ticks add the one it misses.
+        assert_eq!(
+            merged.iter().map(|c| (c.time, c.close)).collect::<Vec<_>>(),
+            vec![(0, 1.0), (3_600, 2.0), (7_200, 4.0)]
+        );
+    }
+}
@@ -1,6 +1,46 @@
-use crate::feeder::{PairPriceInfo, PerpValueInfo, TokenOrPairPriceInfo, TokenPriceInfo};
+use crate::{
+    change::PriceChange,
+    circuit_breaker::EndpointHealth,
+    divergence::DivergenceEvent,
+    feeder::{OraclePriceInfo, PairPriceInfo, PerpValueInfo, TokenOrPairPriceInfo, TokenPriceInfo},
+    jup::quote::QuoteInfo,
+    liquidation::LiquidationAlert,
+    ta::TaSnapshot,
+};
 
 pub fn update_price_display(price_info: &TokenOrPairPriceInfo) -> (String, String) {
+    let (label, formatted_price) = format_label_and_price(price_info);
+
+    // Quoted prices show what the trade costs to route.
+    let formatted_price = match (
+        &price_info.price_info().price,
+        &price_info.price_info().quote,
+    ) {
+        (Some(_), Some(quote)) => format!("{} {}", formatted_price, format_quote(quote)),
+        _ => formatted_price,
+    };
+
+    // Trend arrow and move, e.g. `↗︎+1.24%`.
+    let formatted_price = match (
+        &price_info.price_info().price,
+        &price_info.price_info().change,
+    ) {
+        (Some(_), Some(change)) => match format_change(change) {
+            Some(formatted_change) => format!("{} {}", formatted_price, formatted_change),
+            None => formatted_price,
+        },
+        _ => formatted_price,
+    };
+
+    // Last good value while the latest fetch failed.
+    if price_info.price_info().is_stale {
+        return (label, format!("~{}", formatted_price));
+    }
+
+    (label, formatted_price)
+}
+
+fn format_label_and_price(price_info: &TokenOrPairPriceInfo) -> (String, String) {
     match price_info {
         TokenOrPairPriceInfo::Pair(PairPriceInfo {
             token_a,
@@ -12,20 +52,37 @@ pub fn update_price_display(price_info: &TokenOrPairPriceInfo) -> (String, Strin
                 .price
                 .map(format_price)
                 .unwrap_or("…".to_string());
+            // How far the venues are apart.
+            let formatted_price = match (price_info.price, price_info.spread_bps) {
+                (Some(_), Some(spread_bps)) => {
+                    format!("{} Δ{}", formatted_price, format_bps(spread_bps))
+                }
+                _ => formatted_price,
+            };
             (label, formatted_price)
         }
-        TokenOrPairPriceInfo::Token(TokenPriceInfo { token, price_info }) => {
+        TokenOrPairPriceInfo::Token(TokenPriceInfo { token, price_info })
+        | TokenOrPairPriceInfo::Oracle(OraclePriceInfo {
+            token, price_info, ..
+        }) => {
             let label = token.symbol.to_string();
             let formatted_price = price_info
                 .price
                 .map(format_price_with_dollar)
                 .unwrap_or("…".to_string());
+            // Oracle prices carry how sure they are.
+            let formatted_price = match (price_info.price, price_info.confidence) {
+                (Some(_), Some(confidence)) => {
+                    format!("{} ±{}", formatted_price, format_price(confidence))
+                }
+                _ => formatted_price,
+            };
             (label, formatted_price)
         }
         TokenOrPairPriceInfo::Perp(PerpValueInfo {
-            id: _,
             token,
             pnl_after_fees_usd,
+            ..
         }) => {
             let label = format!("{}🄿", token.symbol);
             let formatted_price = pnl_after_fees_usd
@@ -37,6 +94,105 @@ pub fn update_price_display(price_info: &TokenOrPairPriceInfo) -> (String, Strin
     }
 }
 
+/// Price impact and route length of a quote, e.g. `(0.04%, 2 hops)`.
+pub fn format_quote(quote: &QuoteInfo) -> String {
+    let hops = match quote.hops() {
+        1 => "1 hop".to_owned(),
+        hops => format!("{} hops", hops),
+    };
+    format!("({:.2}%, {})", quote.price_impact_pct, hops)
+}
+
+/// Arrow and percent of the longest window known, e.g. `↗︎+1.24%`.
+pub fn format_change(change: &PriceChange) -> Option<String> {
+    let (_window, pct) = change.headline()?;
+    let glyph = change.trend.map(|trend| trend.glyph()).unwrap_or_default();
+    Some(format!("{}{:+.2}%", glyph, pct))
+}
+
+/// Every window known, e.g. `1h +0.30% · 24h +1.24% · start -0.10%`.
+pub fn format_changes(change: &PriceChange) -> Option<String> {
+    let parts = [
+        ("1h", change.pct_1h),
+        ("24h", change.pct_24h),
+        ("start", change.pct_since_start),
+    ]
+    .iter()
+    .filter_map(|(window, pct)| Some(format!("{} {:+.2}%", window, (*pct)?)))
+    .collect::<Vec<_>>();
+
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// Indicators that are warmed up, e.g. `RSI 70.46 · MACD +0.1234 · BB 168.2–175.1`.
+pub fn format_indicators(ta: &TaSnapshot) -> Option<String> {
+    let mut parts = vec![];
+    if let Some(rsi) = ta.rsi {
+        parts.push(format!("RSI {:.2}", rsi));
+    }
+    if let Some(macd) = ta.macd {
+        parts.push(format!("MACD {:+.4}", macd.histogram));
+    }
+    if let Some(bands) = ta.bollinger {
+        parts.push(format!(
+            "BB {}–{}",
+            format_price(bands.lower),
+            format_price(bands.upper)
+        ));
+    }
+
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// Signed basis points, e.g. `+12.5bp`.
+pub fn format_bps(bps: f64) -> String {
+    format!("{:+.1}bp", bps)
+}
+
+/// Notification title for a venue spread crossing the threshold.
+pub fn format_divergence_event(event: &DivergenceEvent) -> String {
+    match event {
+        DivergenceEvent::Diverged { label, spread, .. } => {
+            format!("{} Raydium vs Jupiter {}", label, format_bps(spread.bps))
+        }
+        DivergenceEvent::Converged { label, spread, .. } => {
+            format!("{} venues back in line {}", label, format_bps(spread.bps))
+        }
+    }
+}
+
+/// Notification title for a position getting close to liquidation.
+pub fn format_liquidation_alert(alert: &LiquidationAlert) -> String {
+    format!(
+        "{} {} {:.1}% from liquidation at {}",
+        alert.market.symbol,
+        alert.side,
+        alert.distance_pct,
+        format_price(alert.liquidation_price)
+    )
+}
+
+/// Flags the perps market closest to liquidation past a threshold, e.g. `⚠︎ SOL 4.2%`.
+pub fn format_liquidation_danger(perps: &[&PerpValueInfo]) -> Option<String> {
+    perps
+        .iter()
+        .filter_map(|perp| Some((perp, perp.liquidation?)))
+        .filter(|(_, status)| status.is_in_danger())
+        .min_by(|(_, a), (_, b)| a.distance_pct.total_cmp(&b.distance_pct))
+        .map(|(perp, status)| format!("⚠︎ {} {:.1}%", perp.token.symbol, status.distance_pct))
+}
+
+/// Names the degraded upstreams, e.g. `⚠︎ api.jup.ag`, or `None` when all are healthy.
+pub fn format_degraded_upstreams(health: &[EndpointHealth]) -> Option<String> {
+    let degraded = health
+        .iter()
+        .filter(|health| health.is_degraded())
+        .map(|health| health.endpoint.as_str())
+        .collect::<Vec<_>>();
+
+    (!degraded.is_empty()).then(|| format!("⚠︎ {}", degraded.join(", ")))
+}
+
 /// Formats a price result into a user-friendly string.
 pub fn format_price_result(result: anyhow::Result<f64>) -> Option<String> {
     result
@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    candle::{Candle, Interval},
+    fetcher::{FetchError, Fetcher},
+    token_registry::Token,
+};
+
+pub const CHARTS_API: &str = "https://fe-api.jup.ag/api/v1/charts";
+
+// Bars asked for per request, longer ranges are split into pages.
+const DEFAULT_PAGE_SIZE: u64 = 500;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Bar {
+    t: u64,
+    o: f64,
+    h: f64,
+    l: f64,
+    c: f64,
+    v: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ChartResponse {
+    bars: Vec<Bar>,
+}
+
+impl From<Bar> for Candle {
+    fn from(bar: Bar) -> Self {
+        Self {
+            time: bar.t,
+            open: bar.o,
+            high: bar.h,
+            low: bar.l,
+            close: bar.c,
+            volume: bar.v,
+        }
+    }
+}
+
+/// Fetches historical OHLCV candles from the Jupiter chart API.
+pub struct CandleFetcher {
+    fetcher: Fetcher,
+    base_url: String,
+    page_size: u64,
+}
+
+impl CandleFetcher {
+    pub fn with_fetcher(fetcher: Fetcher, base_url: &str) -> Self {
+        Self {
+            fetcher,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    /// Limits how many bars each request covers.
+    pub fn with_page_size(mut self, page_size: u64) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Candles of `base` in USD, or in `quote` when given, from `time_from` to `time_to` inclusive.
+    /// Sorted by time without duplicates.
+    pub async fn fetch_candles(
+        &self,
+        base: &Token,
+        quote: Option<&Token>,
+        interval: Interval,
+        time_from: u64,
+        time_to: u64,
+    ) -> Result<Vec<Candle>, FetchError> {
+        let page_secs = self.page_size * interval.secs();
+        // Keyed by time, pages share their boundary bar.
+        let mut candles: BTreeMap<u64, Candle> = BTreeMap::new();
+
+        let mut page_from = interval.floor(time_from);
+        while page_from <= time_to {
+            let page_to = (page_from + page_secs).min(time_to);
+            for candle in self
+                .fetch_page(base, quote, interval, page_from, page_to)
+                .await?
+            {
+                if (time_from..=time_to).contains(&candle.time) {
+                    candles.insert(candle.time, candle);
+                }
+            }
+
+            if page_to == time_to {
+                break;
+            }
+            page_from = page_to;
+        }
+
+        Ok(candles.into_values().collect())
+    }
+
+    /// Candles of a registry pair, `[base, quote]`.
+    pub async fn fetch_pair_candles(
+        &self,
+        pair: &[Token; 2],
+        interval: Interval,
+        time_from: u64,
+        time_to: u64,
+    ) -> Result<Vec<Candle>, FetchError> {
+        let [base, quote] = pair;
+        self.fetch_candles(base, Some(quote), interval, time_from, time_to)
+            .await
+    }
+
+    async fn fetch_page(
+        &self,
+        base: &Token,
+        quote: Option<&Token>,
+        interval: Interval,
+        time_from: u64,
+        time_to: u64,
+    ) -> Result<Vec<Candle>, FetchError> {
+        let quote_param = quote
+            .map(|quote| format!("quote_address={}&", quote.address))
+            .unwrap_or_default();
+        let url = format!(
+            "{}/{}?{}type={}&time_from={}&time_to={}",
+            self.base_url, base.address, quote_param, interval, time_from, time_to
+        );
+
+        self.fetcher
+            .fetch_with_retry(&url, |response: ChartResponse| {
+                Ok(response.bars.into_iter().map(Candle::from).collect())
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fetcher::tests::fixture_fetcher, jup::prices::TokenSymbol, token_registry::TokenRegistry,
+    };
+
+    #[tokio::test]
+    async fn test_fetch_pair_candles_paginated() {
+        let fetcher = CandleFetcher::with_fetcher(fixture_fetcher(), CHARTS_API).with_page_size(2);
+        let token_registry = TokenRegistry::new();
+        let pair = [
+            token_registry
+                .get_by_symbol(&TokenSymbol::JLP)
+                .unwrap()
+                .clone(),
+            token_registry
+                .get_by_symbol(&TokenSymbol::SOL)
+                .unwrap()
+                .clone(),
+        ];
+
+        // 4 hours in pages of 2 bars, the boundary bar comes back twice.
+        let time_from = 1_736_917_200;
+        let candles = fetcher
+            .fetch_pair_candles(&pair, Interval::OneHour, time_from, time_from + 3 * 3_600)
+            .await
+            .unwrap();
+
+        let times = candles.iter().map(|c| c.time).collect::<Vec<_>>();
+        assert_eq!(
+            times,
+            vec![
+                time_from,
+                time_from + 3_600,
+                time_from + 7_200,
+                time_from + 10_800
+            ]
+        );
+        assert_eq!(
+            candles[0],
+            Candle {
+                time: time_from,
+                open: 0.02501,
+                high: 0.02512,
+                low: 0.02495,
+                close: 0.02508,
+                volume: 1520.5,
+            }
+        );
+    }
+}
@@ -1,2 +1,4 @@
+pub mod charts;
 pub mod perps;
 pub mod prices;
+pub mod quote;
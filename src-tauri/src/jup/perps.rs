@@ -1,10 +1,23 @@
-use anyhow::{anyhow, Result};
-use reqwest::Client;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use log::warn;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use strum::EnumString;
 use strum_macros::Display;
 
+use crate::{
+    feeder::TokenOrPairAddress,
+    fetcher::Fetcher,
+    token_registry::{Token, TokenRegistry},
+};
+
+/// Key of the perps PnL of a market, e.g. `So11111111111111111111111111111111111111112_PERPS`.
+pub fn perps_key(market_address: &str) -> TokenOrPairAddress {
+    format!("{market_address}_PERPS")
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PositionsResponse {
@@ -61,25 +74,235 @@ pub struct TpslRequests {
     pub sl: Option<TpslRequest>, // Option to handle null values
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)] // Added Default for TpslRequest as well, though might not be strictly necessary
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct TpslRequest {
-    // Define struct for tp/sl if they are not always null and have a structure
-    // Add fields for tp and sl requests if they are not always null and have a specific structure
-    // Based on the example, they are null, so for now, an empty struct or just Option<TpslRequest> is sufficient
+    pub position_request_pubkey: String,
+    pub trigger_price: String,
+    // USD to close, missing when the whole position closes.
+    #[serde(default)]
+    pub size_usd_delta: Option<String>,
+    pub entire_position: bool,
+    // Token paid out on close.
+    #[serde(default)]
+    pub desired_mint: Option<String>,
+}
+
+/// An amount in a token and its USD value.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct TokenAmount {
+    pub amount: Decimal,
+    pub usd: Decimal,
+}
+
+/// A take profit or stop loss waiting for its trigger price.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TriggerOrder {
+    pub pubkey: String,
+    pub trigger_price: Decimal,
+    // USD to close, `None` closes the whole position.
+    pub size_usd: Option<Decimal>,
+    pub entire_position: bool,
+    // Token paid out on close, resolved when the registry knows the mint.
+    pub desired_mint: Option<String>,
+    pub desired_token: Option<Token>,
+}
+
+/// An open perps position with every amount parsed and every mint resolved.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Position {
+    pub pubkey: String,
+    pub side: Side,
+    pub market: Token,
+    pub collateral_token: Token,
+    pub collateral_usd: Decimal,
+    pub entry_price: Decimal,
+    pub liquidation_price: Decimal,
+    pub leverage: Decimal,
+    pub size_usd: Decimal,
+    // Size in the market token.
+    pub size_amount: Decimal,
+    pub value_usd: Decimal,
+    pub borrow_fees: TokenAmount,
+    pub open_fees: TokenAmount,
+    pub close_fees: TokenAmount,
+    pub total_fees: TokenAmount,
+    pub pnl_before_fees: TokenAmount,
+    pub pnl_after_fees: TokenAmount,
+    // e.g. 6.21 for 6.21%
+    pub pnl_change_pct_before_fees: Decimal,
+    pub pnl_change_pct_after_fees: Decimal,
+    pub take_profit: Option<TriggerOrder>,
+    pub stop_loss: Option<TriggerOrder>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+fn parse_decimal(field: &str, value: &str) -> Result<Decimal> {
+    Decimal::from_str(value).with_context(|| format!("Invalid {}: {}", field, value))
+}
+
+fn parse_time(field: &str, secs: i64) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp(secs, 0).ok_or_else(|| anyhow!("Invalid {}: {}", field, secs))
+}
+
+fn parse_token_amount(field: &str, amount: &str, usd: &str) -> Result<TokenAmount> {
+    Ok(TokenAmount {
+        amount: parse_decimal(field, amount)?,
+        usd: parse_decimal(&format!("{}_usd", field), usd)?,
+    })
+}
+
+fn resolve_mint<'a>(token_registry: &'a TokenRegistry, mint: &str) -> Result<&'a Token> {
+    token_registry
+        .get_by_mint(mint)
+        .ok_or_else(|| anyhow!("Unknown mint: {}", mint))
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+impl TriggerOrder {
+    pub fn from_request(request: &TpslRequest, token_registry: &TokenRegistry) -> Result<Self> {
+        Ok(Self {
+            pubkey: request.position_request_pubkey.clone(),
+            trigger_price: parse_decimal("trigger_price", &request.trigger_price)?,
+            size_usd: request
+                .size_usd_delta
+                .as_deref()
+                .map(|size| parse_decimal("size_usd_delta", size))
+                .transpose()?,
+            entire_position: request.entire_position,
+            desired_mint: request.desired_mint.clone(),
+            desired_token: request
+                .desired_mint
+                .as_deref()
+                .and_then(|mint| token_registry.get_by_mint(mint))
+                .cloned(),
+        })
+    }
+}
+
+impl Position {
+    pub fn from_data(data: &PositionData, token_registry: &TokenRegistry) -> Result<Self> {
+        let trigger_order = |request: &Option<TpslRequest>| {
+            request
+                .as_ref()
+                .map(|request| TriggerOrder::from_request(request, token_registry))
+                .transpose()
+        };
+
+        Ok(Self {
+            pubkey: data.position_pubkey.clone(),
+            side: data.side.clone(),
+            market: resolve_mint(token_registry, &data.market_mint)?.clone(),
+            collateral_token: resolve_mint(token_registry, &data.collateral_mint)?.clone(),
+            collateral_usd: parse_decimal("collateral", &data.collateral)?,
+            entry_price: parse_decimal("entry_price", &data.entry_price)?,
+            liquidation_price: parse_decimal("liquidation_price", &data.liquidation_price)?,
+            leverage: parse_decimal("leverage", &data.leverage)?,
+            size_usd: parse_decimal("size", &data.size)?,
+            size_amount: parse_decimal("size_token_amount", &data.size_token_amount)?,
+            value_usd: parse_decimal("value", &data.value)?,
+            borrow_fees: parse_token_amount(
+                "borrow_fees",
+                &data.borrow_fees,
+                &data.borrow_fees_usd,
+            )?,
+            open_fees: parse_token_amount("open_fees", &data.open_fees, &data.open_fees_usd)?,
+            close_fees: parse_token_amount("close_fees", &data.close_fees, &data.close_fees_usd)?,
+            total_fees: parse_token_amount("total_fees", &data.total_fees, &data.total_fees_usd)?,
+            pnl_before_fees: parse_token_amount(
+                "pnl_before_fees",
+                &data.pnl_before_fees,
+                &data.pnl_before_fees_usd,
+            )?,
+            pnl_after_fees: parse_token_amount(
+                "pnl_after_fees",
+                &data.pnl_after_fees,
+                &data.pnl_after_fees_usd,
+            )?,
+            pnl_change_pct_before_fees: parse_decimal(
+                "pnl_change_pct_before_fees",
+                &data.pnl_change_pct_before_fees,
+            )?,
+            pnl_change_pct_after_fees: parse_decimal(
+                "pnl_change_pct_after_fees",
+                &data.pnl_change_pct_after_fees,
+            )?,
+            take_profit: trigger_order(&data.tpsl_requests.tp)?,
+            stop_loss: trigger_order(&data.tpsl_requests.sl)?,
+            created_at: parse_time("created_time", data.created_time)?,
+            updated_at: parse_time("updated_time", data.updated_time)?,
+        })
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct PositionPNLs {
     pub total_pnl_usd: f64,
     pub total_pnl_percent: f64,
     pub position_pnls: Vec<PositionPNL>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+impl PositionPNLs {
+    pub fn from_positions(positions: &[Position]) -> Self {
+        let mut total_pnl_usd = 0.0;
+        let mut total_pnl_percent = 0.0;
+        let mut position_pnls: Vec<PositionPNL> = Vec::new();
+
+        for position in positions {
+            let pnl_usd = position.pnl_after_fees.usd.to_f64().unwrap_or_default();
+            let pnl_percent = position
+                .pnl_change_pct_after_fees
+                .to_f64()
+                .unwrap_or_default();
+
+            total_pnl_usd += pnl_usd;
+            total_pnl_percent += pnl_percent;
+            position_pnls.push(PositionPNL {
+                position_pubkey: position.pubkey.clone(),
+                side: position.side.clone(),
+                market: position.market.clone(),
+                pnl_usd,
+                pnl_percent,
+            });
+        }
+
+        Self {
+            total_pnl_usd,
+            total_pnl_percent,
+            position_pnls,
+        }
+    }
+
+    /// Totals per market, in the order markets first appear.
+    pub fn by_market(&self) -> Vec<(Token, PositionPNLs)> {
+        let mut markets: Vec<(Token, PositionPNLs)> = Vec::new();
+        for position_pnl in &self.position_pnls {
+            let index = match markets
+                .iter()
+                .position(|(market, _)| market.address == position_pnl.market.address)
+            {
+                Some(index) => index,
+                None => {
+                    markets.push((position_pnl.market.clone(), PositionPNLs::default()));
+                    markets.len() - 1
+                }
+            };
+
+            let pnls = &mut markets[index].1;
+            pnls.total_pnl_usd += position_pnl.pnl_usd;
+            pnls.total_pnl_percent += position_pnl.pnl_percent;
+            pnls.position_pnls.push(position_pnl.clone());
+        }
+
+        markets
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct PositionPNL {
     pub position_pubkey: String,
     pub side: Side,
+    pub market: Token,
     pub pnl_usd: f64,
     pub pnl_percent: f64,
 }
@@ -92,138 +315,256 @@ pub enum Side {
     Short,
 }
 
-const PERPS_API_BASE: &str = "https://perps-api.jup.ag/v1";
+pub const PERPS_API_BASE: &str = "https://perps-api.jup.ag/v1";
 
 pub struct PerpsFetcher {
-    client: Client,
+    fetcher: Fetcher,
+    base_url: String,
 }
 
 impl Default for PerpsFetcher {
     fn default() -> Self {
-        Self {
-            client: Client::new(),
-        }
+        Self::with_fetcher(Fetcher::new(), PERPS_API_BASE)
     }
 }
 
 impl PerpsFetcher {
+    pub fn with_fetcher(fetcher: Fetcher, base_url: &str) -> Self {
+        Self {
+            fetcher,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
     pub async fn fetch_positions(&self, wallet_address: &str) -> Result<PositionsResponse> {
         let url = format!(
             "{}/positions?walletAddress={}",
-            PERPS_API_BASE, wallet_address
+            self.base_url, wallet_address
         );
-        let response = self.client.get(&url).send().await?;
-        if response.status().is_success() {
-            let positions_response: PositionsResponse = response.json().await?;
-            Ok(positions_response)
-        } else {
-            Err(anyhow!(
-                "Failed to fetch positions. Status: {}",
-                response.status()
-            ))
-        }
+        let positions_response = self
+            .fetcher
+            .fetch_with_retry(&url, |response: PositionsResponse| Ok(response))
+            .await?;
+
+        Ok(positions_response)
     }
 
-    pub async fn fetch_positions_pnl_and_format(
+    /// Open positions of a wallet, parsed, with mints resolved through the registry.
+    /// Positions that can't be parsed, e.g. in an unknown market, are skipped.
+    pub async fn fetch_open_positions(
         &self,
         wallet_address: &str,
-    ) -> Result<PositionPNLs> {
+        token_registry: &TokenRegistry,
+    ) -> Result<Vec<Position>> {
         let positions_response = self.fetch_positions(wallet_address).await?;
-        let mut total_pnl_usd = 0.0;
-        let mut total_pnl_percent = 0.0;
-        let mut position_pnls: Vec<PositionPNL> = Vec::new();
 
-        for position in positions_response.data_list {
-            let pnl_usd = position.pnl_after_fees_usd.parse::<f64>().map_err(|_| {
-                anyhow!(
-                    "Failed to parse pnl_after_fees_usd to f64: {}",
-                    position.pnl_after_fees_usd
-                )
-            })?;
-
-            let pnl_percent = position
-                .pnl_change_pct_after_fees
-                .parse::<f64>()
-                .map_err(|_| {
-                    anyhow!(
-                        "Failed to parse pnl_change_pct_after_fees to f64: {}",
-                        position.pnl_change_pct_after_fees
-                    )
-                })?;
+        Ok(positions_response
+            .data_list
+            .iter()
+            .filter_map(|data| match Position::from_data(data, token_registry) {
+                Ok(position) => Some(position),
+                Err(e) => {
+                    warn!("Skipping position {}: {:#}", data.position_pubkey, e);
+                    None
+                }
+            })
+            .collect())
+    }
 
-            total_pnl_usd += pnl_usd;
-            total_pnl_percent += pnl_percent;
-            position_pnls.push(PositionPNL {
-                position_pubkey: position.position_pubkey.clone(),
-                side: position.side.clone(),
-                pnl_usd,
-                pnl_percent,
-            });
-        }
+    pub async fn fetch_positions_pnl_and_format(
+        &self,
+        wallet_address: &str,
+        token_registry: &TokenRegistry,
+    ) -> Result<PositionPNLs> {
+        let positions = self
+            .fetch_open_positions(wallet_address, token_registry)
+            .await?;
 
-        Ok(PositionPNLs {
-            total_pnl_usd,
-            total_pnl_percent,
-            position_pnls,
-        })
+        Ok(PositionPNLs::from_positions(&positions))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{fetcher::tests::fixture_fetcher, jup::prices::TokenSymbol};
+
+    const WALLET_ADDRESS: &str = "7fgGqjCqvSbDLqEfrcWKyd8VWYuyGZ9bD2jjJoSu9jDm";
+    // Short ETH with a take profit and a partial stop loss.
+    const TPSL_WALLET_ADDRESS: &str = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin";
+    // Long BTC.
+    const BTC_WALLET_ADDRESS: &str = "4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T";
+    // A JitoSOL market the registry doesn't know and a SOL long paid out in JitoSOL.
+    const UNKNOWN_MINT_WALLET_ADDRESS: &str = "DZnkkTmCiFWfYTfT41X3Rd1kDgozqzxWaHqsw6W4x2oe";
+
+    fn perps_fetcher() -> PerpsFetcher {
+        PerpsFetcher::with_fetcher(fixture_fetcher(), PERPS_API_BASE)
+    }
 
     #[tokio::test]
     async fn test_fetch_positions() -> Result<()> {
-        let perps_fetcher = PerpsFetcher::default();
-        let wallet_address = "...";
+        let wallet_address = WALLET_ADDRESS;
 
         println!("Fetching positions for wallet: {}", wallet_address);
-        let positions_result = perps_fetcher.fetch_positions(wallet_address).await;
-
-        match positions_result {
-            Ok(positions) => {
-                println!("Successfully fetched positions:");
-                println!("{:#?}", positions); // Using pretty print for better readability
-                if let Some(first_position) = positions.data_list.first() {
-                    println!("\nDetails of the first position:");
-                    println!("  Position Pubkey: {}", first_position.position_pubkey);
-                    println!("  Side: {}", first_position.side);
-                    println!("  Size: {}", first_position.size);
-                    println!(
-                        "  Pnl After Fees Usd: {}",
-                        first_position.pnl_after_fees_usd
-                    );
-                    println!("  Collateral: {}", first_position.collateral);
-                    println!("  Leverage: {}", first_position.leverage);
-                    println!("  Entry Price: {}", first_position.entry_price);
-                    println!("  Liquidation Price: {}", first_position.liquidation_price);
-                    println!("  Total Fees Usd: {}", first_position.total_fees_usd);
-                    println!("  Open Fees Usd: {}", first_position.open_fees_usd);
-                    println!("  Close Fees Usd: {}", first_position.close_fees_usd);
-                    println!("  Borrow Fees Usd: {}", first_position.borrow_fees_usd);
-                    println!("  Created Time: {}", first_position.created_time);
-                    println!("  Updated Time: {}", first_position.updated_time);
-
-                    if let Some(tp_request) = &first_position.tpsl_requests.tp {
-                        println!("  Take Profit Request: {:?}", tp_request);
-                    } else {
-                        println!("  Take Profit Request: None");
-                    }
-
-                    if let Some(sl_request) = &first_position.tpsl_requests.sl {
-                        println!("  Stop Loss Request: {:?}", sl_request);
-                    } else {
-                        println!("  Stop Loss Request: None");
-                    }
-                } else {
-                    println!("No positions found in the response.");
-                }
-            }
-            Err(e) => {
-                eprintln!("Error fetching positions: {:?}", e);
-            }
-        }
+        let positions = perps_fetcher().fetch_positions(wallet_address).await?;
+        println!("{:#?}", positions); // Using pretty print for better readability
+
+        assert_eq!(positions.count, 1);
+        let first_position = positions.data_list.first().expect("one position");
+        println!("\nDetails of the first position:");
+        println!("  Position Pubkey: {}", first_position.position_pubkey);
+        println!("  Side: {}", first_position.side);
+        println!("  Size: {}", first_position.size);
+        println!(
+            "  Pnl After Fees Usd: {}",
+            first_position.pnl_after_fees_usd
+        );
+        println!("  Leverage: {}", first_position.leverage);
+        println!("  Entry Price: {}", first_position.entry_price);
+        println!("  Liquidation Price: {}", first_position.liquidation_price);
+
+        assert_eq!(first_position.side, Side::Long);
+        assert!(first_position.tpsl_requests.tp.is_none());
+        assert!(first_position.tpsl_requests.sl.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_positions_pnl_and_format() -> Result<()> {
+        let pnls = perps_fetcher()
+            .fetch_positions_pnl_and_format(WALLET_ADDRESS, &TokenRegistry::new())
+            .await?;
+
+        assert_eq!(pnls.position_pnls.len(), 1);
+        assert_eq!(pnls.total_pnl_usd, 12.73);
+        assert_eq!(pnls.position_pnls[0].market.symbol, TokenSymbol::SOL);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_open_positions() -> Result<()> {
+        let token_registry = TokenRegistry::new();
+        let positions = perps_fetcher()
+            .fetch_open_positions(WALLET_ADDRESS, &token_registry)
+            .await?;
+
+        let position = &positions[0];
+        assert_eq!(position.market.symbol, TokenSymbol::SOL);
+        assert_eq!(position.collateral_token.symbol, TokenSymbol::USDC);
+        assert_eq!(position.entry_price, Decimal::new(16895, 2));
+        assert_eq!(position.pnl_after_fees.usd, Decimal::new(1273, 2));
+        assert_eq!(position.total_fees.amount, Decimal::new(7096, 6));
+        assert_eq!(position.created_at.timestamp(), 1739512811);
+        assert_eq!(position.take_profit, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_positions_with_tpsl() -> Result<()> {
+        let token_registry = TokenRegistry::new();
+        let positions = perps_fetcher()
+            .fetch_open_positions(TPSL_WALLET_ADDRESS, &token_registry)
+            .await?;
+
+        let position = &positions[0];
+        assert_eq!(position.side, Side::Short);
+        assert_eq!(position.market.symbol, TokenSymbol::ETH);
+
+        let take_profit = position.take_profit.as_ref().expect("take profit");
+        assert_eq!(take_profit.trigger_price, Decimal::new(2500, 0));
+        assert!(take_profit.entire_position);
+        assert_eq!(take_profit.size_usd, None);
+        assert_eq!(
+            take_profit.desired_token.as_ref().map(|token| token.symbol),
+            Some(TokenSymbol::USDC)
+        );
+
+        let stop_loss = position.stop_loss.as_ref().expect("stop loss");
+        assert_eq!(stop_loss.trigger_price, Decimal::new(285050, 2));
+        assert_eq!(stop_loss.size_usd, Some(Decimal::new(500, 0)));
+        assert!(!stop_loss.entire_position);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pnls_by_market() {
+        let token_registry = TokenRegistry::new();
+        let market = |symbol| {
+            token_registry
+                .perps_markets
+                .iter()
+                .find(|market| market.symbol == symbol)
+                .unwrap()
+                .clone()
+        };
+        let position_pnl = |market: Token, side, pnl_usd| PositionPNL {
+            position_pubkey: format!("{}_{}", market.symbol, pnl_usd),
+            side,
+            market,
+            pnl_usd,
+            pnl_percent: 1.0,
+        };
+        let pnls = PositionPNLs {
+            total_pnl_usd: 8.0,
+            total_pnl_percent: 3.0,
+            position_pnls: vec![
+                position_pnl(market(TokenSymbol::SOL), Side::Long, 10.0),
+                position_pnl(market(TokenSymbol::ETH), Side::Short, 2.0),
+                position_pnl(market(TokenSymbol::SOL), Side::Short, -4.0),
+            ],
+        };
+
+        let markets = pnls.by_market();
+
+        let totals = markets
+            .iter()
+            .map(|(market, pnls)| (market.symbol, pnls.total_pnl_usd, pnls.position_pnls.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            totals,
+            vec![(TokenSymbol::SOL, 6.0, 2), (TokenSymbol::ETH, 2.0, 1)]
+        );
+        assert_eq!(
+            perps_key(&markets[1].0.address),
+            "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs_PERPS"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_btc_position() -> Result<()> {
+        let positions = perps_fetcher()
+            .fetch_open_positions(BTC_WALLET_ADDRESS, &TokenRegistry::new())
+            .await?;
+
+        let position = &positions[0];
+        assert_eq!(position.market.symbol, TokenSymbol::BTC);
+        assert_eq!(position.market.decimals, 8);
+        assert_eq!(position.size_amount, Decimal::new(795762, 8));
+        assert_eq!(position.liquidation_price, Decimal::new(6521040, 2));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unknown_mints_dont_fail_the_wallet() -> Result<()> {
+        let positions = perps_fetcher()
+            .fetch_open_positions(UNKNOWN_MINT_WALLET_ADDRESS, &TokenRegistry::new())
+            .await?;
+
+        // The JitoSOL market is skipped, the SOL long is kept.
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].market.symbol, TokenSymbol::SOL);
+
+        let take_profit = positions[0].take_profit.as_ref().expect("take profit");
+        assert_eq!(take_profit.desired_token, None);
+        assert_eq!(
+            take_profit.desired_mint.as_deref(),
+            Some("J1toso1uCk3RLmjorhTtrVwY9HJ7X8V9yYac6Y7kGCPn")
+        );
 
         Ok(())
     }
@@ -1,4 +1,7 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{future::join, stream, StreamExt};
+use log::warn;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use strum::AsRefStr;
@@ -6,8 +9,9 @@ use strum_macros::{Display, EnumString};
 
 use crate::{
     feeder::{PriceInfo, TokenOrPairAddress, TokenOrPairPriceInfo, TokenPriceInfo},
-    fetcher::{Fetcher, RetrySettings},
+    fetcher::{FetchError, Fetcher, RetrySettings},
     formatter::{format_price, format_price_result},
+    source::{PriceSource, PriceSourceKind},
     time::get_unix_timestamp,
     token_registry::Token,
 };
@@ -38,10 +42,11 @@ pub enum TokenSymbol {
     JLP,
     JUP,
     USDC,
+    USDT,
+    ETH,
+    BTC,
     #[allow(non_camel_case_types)]
     JupSOL,
-    #[allow(non_camel_case_types)]
-    SOL_PERPS,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -54,77 +59,100 @@ struct TokenData {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PriceResponse {
-    data: HashMap<String, TokenData>,
+    data: HashMap<String, Option<TokenData>>,
     time_taken: f64,
 }
 
-const JUP_API: &str = "https://api.jup.ag/price/v2";
+pub const JUP_API: &str = "https://api.jup.ag/price/v2";
+
+// Pair requests allowed in flight at once.
+const DEFAULT_PAIR_CONCURRENCY: usize = 8;
 
 /// A dedicated struct for fetching prices.
 pub struct PriceFetcher {
     fetcher: Fetcher,
+    base_url: String,
+    pair_concurrency: usize,
 }
 
 impl Default for PriceFetcher {
     fn default() -> Self {
-        Self {
-            fetcher: Fetcher::new(),
-        }
+        Self::new()
     }
 }
 
 impl PriceFetcher {
     /// Creates a new `PriceFetcher` with default settings.
     pub fn new() -> Self {
-        Self {
-            fetcher: Fetcher::new(),
-        }
+        Self::with_fetcher(Fetcher::new(), JUP_API)
     }
 
     /// Creates a new `PriceFetcher` with custom settings.
     pub fn with_settings(settings: RetrySettings) -> Self {
+        Self::with_fetcher(Fetcher::with_settings(settings), JUP_API)
+    }
+
+    /// Creates a new `PriceFetcher` on a shared fetcher and price API base URL.
+    pub fn with_fetcher(fetcher: Fetcher, base_url: &str) -> Self {
         Self {
-            fetcher: Fetcher::with_settings(settings),
+            fetcher,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            pair_concurrency: DEFAULT_PAIR_CONCURRENCY,
         }
     }
 
+    /// Limits how many pair requests run at once.
+    pub fn with_pair_concurrency(mut self, pair_concurrency: usize) -> Self {
+        self.pair_concurrency = pair_concurrency.max(1);
+        self
+    }
+
     /// Fetches the price of a single token.
-    pub async fn fetch_price(&self, address: &str) -> Result<f64> {
-        let url = format!("{JUP_API}?ids={}", address);
+    pub async fn fetch_price(&self, address: &str) -> Result<f64, FetchError> {
+        let url = format!("{}?ids={}", self.base_url, address);
         self.fetch_price_internal(&url).await.and_then(|mut map| {
             map.remove(address)
-                .ok_or_else(|| anyhow!("Token {} not found", address))
+                .ok_or_else(|| FetchError::NotFound(format!("Token {}", address)))
         })
     }
 
     /// Fetches the price of a token pair.
-    pub async fn fetch_pair_price(&self, base: &str, vs: &str) -> Result<f64> {
-        let url = format!("{JUP_API}?ids={}&vsToken={}", base, vs);
+    pub async fn fetch_pair_price(&self, base: &str, vs: &str) -> Result<f64, FetchError> {
+        let url = format!("{}?ids={}&vsToken={}", self.base_url, base, vs);
         self.fetch_price_internal(&url).await.and_then(|mut map| {
             map.remove(base)
-                .ok_or_else(|| anyhow!("Base token {} not found", base))
+                .ok_or_else(|| FetchError::NotFound(format!("Base token {}", base)))
         })
     }
 
     /// Fetches prices for multiple tokens.
-    pub async fn fetch_many_prices(&self, addresses: &[&str]) -> Result<HashMap<String, f64>> {
+    pub async fn fetch_many_prices(
+        &self,
+        addresses: &[&str],
+    ) -> Result<HashMap<String, f64>, FetchError> {
         let params = addresses.join(",");
-        let url = format!("{JUP_API}?ids={}", params);
+        let url = format!("{}?ids={}", self.base_url, params);
         self.fetch_price_internal(&url).await
     }
 
-    /// Shared logic for fetching prices.
-    async fn fetch_price_internal(&self, url: &str) -> Result<HashMap<String, f64>> {
+    /// Shared logic for fetching prices, unknown tokens come back as null and are skipped.
+    async fn fetch_price_internal(&self, url: &str) -> Result<HashMap<String, f64>, FetchError> {
         self.fetcher
             .fetch_with_retry(url, |response: PriceResponse| {
                 response
                     .data
                     .iter()
+                    .filter_map(|(address, data)| data.as_ref().map(|data| (address, data)))
                     .map(|(address, data)| {
                         data.price
                             .parse::<f64>()
                             .map(|price| (address.clone(), price))
-                            .map_err(|e| anyhow!("Failed to parse price for {}: {}", address, e))
+                            .map_err(|e| {
+                                FetchError::Decode(format!(
+                                    "Failed to parse price for {}: {}",
+                                    address, e
+                                ))
+                            })
                     })
                     .collect()
             })
@@ -135,72 +163,262 @@ impl PriceFetcher {
     pub async fn fetch_price_and_format(&self, tokens: Vec<Token>) -> Option<String> {
         let is_pair = tokens.len() == 2;
         if !is_pair {
-            format_price_result(self.fetch_price(&tokens[0].address).await)
+            format_price_result(
+                self.fetch_price(&tokens[0].address)
+                    .await
+                    .map_err(Into::into),
+            )
         } else {
             format_price_result(
                 self.fetch_pair_price(&tokens[0].address, &tokens[1].address)
-                    .await,
+                    .await
+                    .map_err(Into::into),
             )
         }
     }
 
+    /// Prices every token and pair it can, failed keys carry their error instead of a price.
     pub async fn fetch_many_price_and_format(
         &self,
         single_tokens: Vec<Token>,
         pairs: Vec<[Token; 2]>,
-    ) -> Option<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>> {
+    ) -> HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> {
         let mut all_prices: HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> = HashMap::new();
 
-        // Fetch single token prices
-        if !single_tokens.is_empty() {
-            let single_addresses: Vec<&str> =
-                single_tokens.iter().map(|t| t.address.as_str()).collect();
-
-            if let Ok(prices) = self.fetch_many_prices(&single_addresses).await {
-                for token in single_tokens {
-                    if let Some(price) = prices.get(token.address.as_str()) {
-                        all_prices.insert(
-                            token.address.clone() as TokenOrPairAddress,
-                            TokenOrPairPriceInfo::Token(TokenPriceInfo {
-                                token: token.clone(),
-                                price_info: PriceInfo {
-                                    price: Some(*price),
-                                    formatted_price: format_price(*price),
-                                    updated_at: get_unix_timestamp(),
-                                },
-                            }),
-                        );
-                    }
-                }
+        // Single tokens go in one batch call while pairs are fetched concurrently.
+        let single_addresses: Vec<&str> =
+            single_tokens.iter().map(|t| t.address.as_str()).collect();
+        let singles_future = async {
+            if single_addresses.is_empty() {
+                Ok(HashMap::new())
             } else {
-                return None; // Or handle the error as needed
+                self.fetch_many_prices(&single_addresses).await
             }
+        };
+        let pairs_future = stream::iter(pairs)
+            .map(|[token_a, token_b]| async move {
+                let price = self
+                    .fetch_pair_price(&token_a.address, &token_b.address)
+                    .await;
+                ([token_a, token_b], price)
+            })
+            .buffer_unordered(self.pair_concurrency)
+            .collect::<Vec<_>>();
+        let (single_prices, pair_prices) = join(singles_future, pairs_future).await;
+
+        // Fetch single token prices
+        for token in single_tokens {
+            let price = match &single_prices {
+                Ok(prices) => prices
+                    .get(token.address.as_str())
+                    .copied()
+                    .ok_or_else(|| format!("Token {} not found", token.address)),
+                Err(e) => Err(e.to_string()),
+            };
+            let price_info = match price {
+                Ok(price) => Self::price_info(price),
+                Err(e) => {
+                    warn!("Failed to price {}: {}", token.symbol, e);
+                    PriceInfo::failed(e)
+                }
+            };
+
+            all_prices.insert(
+                token.address.clone() as TokenOrPairAddress,
+                TokenOrPairPriceInfo::Token(TokenPriceInfo { token, price_info }),
+            );
         }
 
         // Fetch pair prices
-        for [token_a, token_b] in pairs {
-            // Directly iterate over the pairs
-            if let Ok(price) = self
-                .fetch_pair_price(&token_a.address, &token_b.address)
-                .await
-            {
-                all_prices.insert(
-                    format!("{}_{}", token_a.address, token_b.address) as TokenOrPairAddress,
-                    TokenOrPairPriceInfo::Pair(crate::feeder::PairPriceInfo {
-                        token_a: token_a.clone(),
-                        token_b: token_b.clone(),
-                        price_info: PriceInfo {
-                            price: Some(price),
-                            formatted_price: format_price(price),
-                            updated_at: get_unix_timestamp(),
-                        },
-                    }),
-                );
-            } else {
-                return None; // Or handle the error as needed
-            }
+        for ([token_a, token_b], price) in pair_prices {
+            let price_info = match price {
+                Ok(price) => Self::price_info(price),
+                Err(e) => {
+                    warn!(
+                        "Failed to price {}/{}: {}",
+                        token_a.symbol, token_b.symbol, e
+                    );
+                    PriceInfo::failed(e)
+                }
+            };
+
+            all_prices.insert(
+                format!("{}_{}", token_a.address, token_b.address) as TokenOrPairAddress,
+                TokenOrPairPriceInfo::Pair(crate::feeder::PairPriceInfo {
+                    token_a,
+                    token_b,
+                    price_info,
+                }),
+            );
         }
 
-        Some(all_prices)
+        all_prices
+    }
+
+    fn price_info(price: f64) -> PriceInfo {
+        PriceInfo {
+            price: Some(price),
+            formatted_price: format_price(price),
+            updated_at: get_unix_timestamp(),
+            source: Some(PriceSourceKind::Jupiter),
+            ..Default::default()
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for PriceFetcher {
+    fn kind(&self) -> PriceSourceKind {
+        PriceSourceKind::Jupiter
+    }
+
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    async fn fetch_prices(
+        &self,
+        single_tokens: &[Token],
+        pairs: &[[Token; 2]],
+    ) -> Result<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>> {
+        Ok(self
+            .fetch_many_price_and_format(single_tokens.to_vec(), pairs.to_vec())
+            .await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::tests::{fixture_fetcher, ok_response, serve};
+    use crate::token_registry::tests::token;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::time::{sleep, Duration, Instant};
+
+    /// Slow price API that answers every `ids` with a price and tracks peak concurrency.
+    async fn serve_slow_prices(delay: Duration, peak: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (in_flight, peak) = (in_flight.clone(), peak.clone());
+                tokio::spawn(async move {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(current, Ordering::SeqCst);
+
+                    let mut buf = [0u8; 1024];
+                    let size = socket.read(&mut buf).await.unwrap();
+                    let request = String::from_utf8_lossy(&buf[..size]);
+                    let target = request.split_whitespace().nth(1).unwrap_or_default();
+                    let ids = target
+                        .split(['?', '&'])
+                        .find_map(|param| param.strip_prefix("ids="))
+                        .unwrap_or_default();
+                    let data = ids
+                        .split(',')
+                        .map(|id| match id.starts_with("delisted") {
+                            true => format!(r#""{id}":null"#),
+                            false => format!(r#""{id}":{{"price":"1.5","type":"derivedPrice"}}"#),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",");
+
+                    sleep(delay).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let response = ok_response(&format!(r#"{{"data":{{{data}}},"timeTaken":0}}"#));
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+
+        format!("http://{address}")
+    }
+
+    #[tokio::test]
+    async fn test_fetch_many_pairs_concurrently_with_limit() {
+        let peak = Arc::new(AtomicUsize::new(0));
+        let delay = Duration::from_millis(200);
+        let base_url = serve_slow_prices(delay, peak.clone()).await;
+        let price_fetcher =
+            PriceFetcher::with_fetcher(Fetcher::new(), &base_url).with_pair_concurrency(4);
+
+        let quote = token("sol", TokenSymbol::SOL);
+        let pairs = (0..8)
+            .map(|i| [token(&format!("base{i}"), TokenSymbol::JLP), quote.clone()])
+            .collect::<Vec<_>>();
+
+        let started_at = Instant::now();
+        let prices = price_fetcher
+            .fetch_many_price_and_format(vec![quote.clone()], pairs)
+            .await;
+
+        assert_eq!(prices.len(), 9);
+        assert!(prices.contains_key("base7_sol"));
+        assert!(peak.load(Ordering::SeqCst) <= 5);
+        // 8 pairs at 4 in flight is two rounds, not eight.
+        assert!(started_at.elapsed() < delay * 4);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_price_from_configured_base_url() {
+        let base_url = serve(vec![
+            ok_response(
+                r#"{"data":{"So11111111111111111111111111111111111111112":{"id":"So11111111111111111111111111111111111111112","type":"derivedPrice","price":"201.5"}},"timeTaken":0.01}"#,
+            ),
+            ok_response(r#"{"data":{"unknown":null},"timeTaken":0.01}"#),
+        ])
+        .await;
+        let price_fetcher = PriceFetcher::with_fetcher(Fetcher::new(), &base_url);
+
+        let price = price_fetcher
+            .fetch_price("So11111111111111111111111111111111111111112")
+            .await
+            .unwrap();
+        assert_eq!(price, 201.5);
+
+        let error = price_fetcher.fetch_price("unknown").await.unwrap_err();
+        assert!(matches!(error, FetchError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn test_failed_pair_does_not_hide_others() {
+        let base_url = serve_slow_prices(Duration::ZERO, Default::default()).await;
+        let price_fetcher = PriceFetcher::with_fetcher(Fetcher::new(), &base_url);
+
+        let quote = token("sol", TokenSymbol::SOL);
+        let prices = price_fetcher
+            .fetch_many_price_and_format(
+                vec![quote.clone(), token("delisted_token", TokenSymbol::JUP)],
+                vec![
+                    [token("jlp", TokenSymbol::JLP), quote.clone()],
+                    [token("delisted_pair", TokenSymbol::JUP), quote.clone()],
+                ],
+            )
+            .await;
+
+        assert_eq!(prices.len(), 4);
+        assert_eq!(prices["sol"].price_info().price, Some(1.5));
+        assert_eq!(prices["jlp_sol"].price_info().price, Some(1.5));
+        assert!(prices["delisted_token"].price_info().error.is_some());
+        assert!(!prices["delisted_pair_sol"].is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_many_prices_replayed() {
+        let fetcher = PriceFetcher::with_fetcher(fixture_fetcher(), JUP_API);
+        let sol = "So11111111111111111111111111111111111111112";
+        let jlp = "27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4";
+
+        let prices = fetcher.fetch_many_prices(&[sol, jlp]).await.unwrap();
+
+        assert_eq!(prices[sol], 172.64);
+        assert_eq!(prices[jlp], 4.31);
     }
 }
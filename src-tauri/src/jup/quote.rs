@@ -0,0 +1,314 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{
+    feeder::{PairPriceInfo, PriceInfo, TokenOrPairAddress, TokenOrPairPriceInfo, TokenPriceInfo},
+    fetcher::{FetchError, Fetcher},
+    formatter::format_price,
+    jup::prices::TokenSymbol,
+    source::{PriceSource, PriceSourceKind},
+    time::get_unix_timestamp,
+    token_registry::{get_pair_ot_token_address_from_tokens, Token},
+};
+
+pub const JUP_QUOTE_API: &str = "https://api.jup.ag/swap/v1/quote";
+
+// Quote requests allowed in flight at once.
+const QUOTE_CONCURRENCY: usize = 4;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapInfo {
+    pub amm_key: String,
+    pub label: Option<String>,
+    pub input_mint: String,
+    pub output_mint: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutePlan {
+    pub swap_info: SwapInfo,
+    pub percent: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteResponse {
+    pub input_mint: String,
+    pub in_amount: String,
+    pub output_mint: String,
+    pub out_amount: String,
+    pub price_impact_pct: String,
+    pub route_plan: Vec<RoutePlan>,
+}
+
+/// How an executable price was obtained.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct QuoteInfo {
+    // Trade size in the quote token, e.g. 10 for 10 SOL.
+    pub notional: f64,
+    // e.g. 0.12 for 0.12%
+    pub price_impact_pct: f64,
+    // AMM labels along the route, e.g. `["Raydium CLMM", "Meteora DLMM"]`.
+    pub route: Vec<String>,
+}
+
+impl QuoteInfo {
+    pub fn hops(&self) -> usize {
+        self.route.len()
+    }
+}
+
+impl QuoteResponse {
+    /// Price of the output token in the input token, adjusted by the given mint decimals.
+    pub fn effective_price(&self, input_decimals: u8, output_decimals: u8) -> Result<f64> {
+        let in_amount = self.in_amount.parse::<f64>()? / 10f64.powi(input_decimals as i32);
+        let out_amount = self.out_amount.parse::<f64>()? / 10f64.powi(output_decimals as i32);
+        if out_amount == 0.0 {
+            return Err(anyhow!("Quote has no output"));
+        }
+
+        Ok(in_amount / out_amount)
+    }
+
+    pub fn quote_info(&self, notional: f64) -> QuoteInfo {
+        QuoteInfo {
+            notional,
+            // The API returns a fraction, e.g. "0.0012" for 0.12%.
+            price_impact_pct: self.price_impact_pct.parse::<f64>().unwrap_or_default() * 100.0,
+            route: self
+                .route_plan
+                .iter()
+                .map(|plan| {
+                    plan.swap_info
+                        .label
+                        .clone()
+                        .unwrap_or_else(|| plan.swap_info.amm_key.clone())
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Fetches swap quotes from the Jupiter quote API.
+pub struct QuoteFetcher {
+    fetcher: Fetcher,
+    base_url: String,
+    slippage_bps: u16,
+}
+
+impl QuoteFetcher {
+    pub fn with_fetcher(fetcher: Fetcher, base_url: &str) -> Self {
+        Self {
+            fetcher,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            slippage_bps: 50,
+        }
+    }
+
+    pub fn with_slippage_bps(mut self, slippage_bps: u16) -> Self {
+        self.slippage_bps = slippage_bps;
+        self
+    }
+
+    /// Quotes selling `amount` of `input` for `output`, in UI units.
+    pub async fn fetch_quote(
+        &self,
+        input: &Token,
+        output: &Token,
+        amount: f64,
+    ) -> Result<QuoteResponse, FetchError> {
+        let raw_amount = (amount * 10f64.powi(input.decimals as i32)).round() as u64;
+        let url = format!(
+            "{}?inputMint={}&outputMint={}&amount={}&slippageBps={}",
+            self.base_url, input.address, output.address, raw_amount, self.slippage_bps
+        );
+
+        self.fetcher
+            .fetch_with_retry(&url, |response: QuoteResponse| Ok(response))
+            .await
+    }
+
+    /// Price of `base` in `quote` when buying `base` with `notional` of `quote`.
+    pub async fn fetch_effective_price(
+        &self,
+        base: &Token,
+        quote: &Token,
+        notional: f64,
+    ) -> Result<(f64, QuoteInfo)> {
+        let response = self.fetch_quote(quote, base, notional).await?;
+        let price = response.effective_price(quote.decimals, base.decimals)?;
+
+        Ok((price, response.quote_info(notional)))
+    }
+}
+
+/// Prices what a trade of the configured size would actually fill at.
+pub struct QuotePriceSource {
+    fetcher: QuoteFetcher,
+    // Single tokens are quoted against this, e.g. USDC.
+    usd_token: Token,
+    // Trade size per quote token, tokens quoted in anything else are left to the next source.
+    notionals: HashMap<TokenSymbol, f64>,
+}
+
+impl QuotePriceSource {
+    pub fn new(
+        fetcher: QuoteFetcher,
+        usd_token: Token,
+        notionals: HashMap<TokenSymbol, f64>,
+    ) -> Self {
+        Self {
+            fetcher,
+            usd_token,
+            notionals,
+        }
+    }
+
+    /// Quotes `(is_pair, base, quote, notional)` requests concurrently.
+    async fn fetch_quotes(
+        &self,
+        requests: Vec<(bool, Token, Token, f64)>,
+    ) -> Vec<(bool, Token, Token, Result<(f64, QuoteInfo)>)> {
+        stream::iter(requests)
+            .map(|(is_pair, base, quote, notional)| async move {
+                let result = self
+                    .fetcher
+                    .fetch_effective_price(&base, &quote, notional)
+                    .await;
+                (is_pair, base, quote, result)
+            })
+            .buffer_unordered(QUOTE_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    fn price_info(price: f64, quote: QuoteInfo) -> PriceInfo {
+        PriceInfo {
+            price: Some(price),
+            formatted_price: format_price(price),
+            updated_at: get_unix_timestamp(),
+            source: Some(PriceSourceKind::JupiterQuote),
+            quote: Some(quote),
+            ..Default::default()
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for QuotePriceSource {
+    fn kind(&self) -> PriceSourceKind {
+        PriceSourceKind::JupiterQuote
+    }
+
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    async fn fetch_prices(
+        &self,
+        single_tokens: &[Token],
+        pairs: &[[Token; 2]],
+    ) -> Result<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>> {
+        // Singles are pairs against the USD token.
+        let requests = single_tokens
+            .iter()
+            .filter(|token| token.address != self.usd_token.address)
+            .map(|token| (false, [token.clone(), self.usd_token.clone()]))
+            .chain(pairs.iter().map(|pair| (true, pair.clone())))
+            .filter_map(|(is_pair, [base, quote])| {
+                let notional = *self.notionals.get(&quote.symbol)?;
+                Some((is_pair, base, quote, notional))
+            })
+            .collect::<Vec<_>>();
+
+        let mut all_prices: HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> = HashMap::new();
+        for (is_pair, base, quote, result) in self.fetch_quotes(requests).await {
+            let price_info = match result {
+                Ok((price, quote_info)) => Self::price_info(price, quote_info),
+                Err(e) => {
+                    warn!("Failed to quote {}/{}: {}", base.symbol, quote.symbol, e);
+                    PriceInfo::failed(e)
+                }
+            };
+
+            if is_pair {
+                all_prices.insert(
+                    get_pair_ot_token_address_from_tokens(&[base.clone(), quote.clone()])?,
+                    TokenOrPairPriceInfo::Pair(PairPriceInfo {
+                        token_a: base,
+                        token_b: quote,
+                        price_info,
+                    }),
+                );
+            } else {
+                all_prices.insert(
+                    base.address.clone(),
+                    TokenOrPairPriceInfo::Token(TokenPriceInfo {
+                        token: base,
+                        price_info,
+                    }),
+                );
+            }
+        }
+
+        Ok(all_prices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fetcher::tests::fixture_fetcher, token_registry::TokenRegistry};
+
+    #[tokio::test]
+    async fn test_fetch_effective_price_replayed() {
+        let token_registry = TokenRegistry::new();
+        let sol = token_registry.get_by_symbol(&TokenSymbol::SOL).unwrap();
+        let jlp = token_registry.get_by_symbol(&TokenSymbol::JLP).unwrap();
+        let fetcher = QuoteFetcher::with_fetcher(fixture_fetcher(), JUP_QUOTE_API);
+
+        // 10 SOL buys 399.5 JLP over two hops.
+        let (price, quote) = fetcher.fetch_effective_price(jlp, sol, 10.0).await.unwrap();
+
+        assert!((price - 10.0 / 399.5).abs() < 1e-12);
+        assert!((quote.price_impact_pct - 0.042).abs() < 1e-9);
+        assert_eq!(quote.route, vec!["Raydium CLMM", "Meteora DLMM"]);
+        assert_eq!(quote.hops(), 2);
+        assert_eq!(quote.notional, 10.0);
+    }
+
+    #[tokio::test]
+    async fn test_quote_source_skips_unconfigured_quote_tokens() {
+        let token_registry = TokenRegistry::new();
+        let sol = token_registry.get_by_symbol(&TokenSymbol::SOL).unwrap();
+        let jlp = token_registry.get_by_symbol(&TokenSymbol::JLP).unwrap();
+        let usdc = token_registry
+            .stable_tokens
+            .iter()
+            .find(|token| token.symbol == TokenSymbol::USDC)
+            .unwrap();
+        let source = QuotePriceSource::new(
+            QuoteFetcher::with_fetcher(fixture_fetcher(), JUP_QUOTE_API),
+            usdc.clone(),
+            HashMap::from([(TokenSymbol::SOL, 10.0)]),
+        );
+
+        // No USDC notional, so the single is left to the next source.
+        let prices = source
+            .fetch_prices(std::slice::from_ref(sol), &[[jlp.clone(), sol.clone()]])
+            .await
+            .unwrap();
+
+        assert_eq!(prices.len(), 1);
+        let pair = &prices[&format!("{}_{}", jlp.address, sol.address)];
+        let price_info = pair.price_info();
+        assert_eq!(price_info.source, Some(PriceSourceKind::JupiterQuote));
+        assert_eq!(price_info.quote.as_ref().map(QuoteInfo::hops), Some(2));
+    }
+}
@@ -1,35 +1,66 @@
 pub mod assets;
+pub mod candle;
+pub mod change;
+pub mod circuit_breaker;
 pub mod commands;
-// pub mod config;
+pub mod config;
+pub mod divergence;
 pub mod feeder;
 pub mod fetcher;
+pub mod forecast;
 pub mod formatter;
 pub mod jup;
+pub mod liquidation;
+pub mod pyth;
 pub mod ray;
 pub mod runner;
+pub mod solana;
+pub mod source;
+pub mod store;
+pub mod stream;
+pub mod ta;
 pub mod time;
 pub mod token_registry;
 pub mod tray;
+pub mod volatility;
 
 use chrono::Local;
-use commands::core::{greet, update_token_and_price};
-use feeder::{TokenOrPairAddress, TokenOrPairPriceInfo};
-use formatter::update_price_display;
+use commands::core::{
+    flip_pair, get_forecast, get_indicators, get_price_history, get_range_stability,
+    get_upstream_health, greet, update_token_and_price,
+};
+use config::{ApiConfig, Config, MonitorSettings};
+use feeder::{get_price_info, TokenOrPairAddress, TokenOrPairPriceInfo};
+use fetcher::Fetcher;
+use formatter::{
+    format_changes, format_degraded_upstreams, format_divergence_event, format_indicators,
+    format_liquidation_alert, format_liquidation_danger, update_price_display,
+};
 use jup::prices::TokenSymbol;
-use log::LevelFilter;
-use runner::run_loop;
+use log::{error, warn, LevelFilter};
+use runner::{run_loop, Monitors};
 use std::io::Write;
+use store::{PriceStore, RetentionPolicy};
 use tauri_plugin_fs::FsExt;
 
 use tauri::{
-    menu::Menu, tray::TrayIconId, LogicalSize, Manager, RunEvent, Url, WebviewUrl,
+    menu::Menu, tray::TrayIconId, Emitter, LogicalSize, Manager, RunEvent, Url, WebviewUrl,
     WebviewWindowBuilder,
 };
-use token_registry::{get_pair_ot_token_address_from_tokens, Token, TokenRegistry};
-use tokio::sync::watch::{self};
-use tray::setup_tray;
+use tauri_plugin_notification::NotificationExt;
+use token_registry::{
+    get_pair_ot_token_address_from_tokens, Token, TokenRegistry, INVERTED_PAIRS_FILE,
+};
+use tokio::sync::{
+    mpsc,
+    watch::{self},
+};
+use tray::{setup_tray, sync_perps_menu_items};
 
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 #[allow(dead_code)]
 #[derive(Clone)]
@@ -57,34 +88,19 @@ pub struct AppState {
     price_targets: Mutex<Vec<PriceTarget>>,
     price_watches: Mutex<Vec<String>>,
     current_public_key: Mutex<Option<String>>,
+    api_config: Mutex<ApiConfig>,
+    // Shared by every fetcher, see `ApiConfig`.
+    fetcher: Mutex<Fetcher>,
+    retention_policy: Mutex<RetentionPolicy>,
+    monitor_settings: Mutex<MonitorSettings>,
+    // Price history, `None` when the database could not be opened.
+    price_store: Mutex<Option<Arc<PriceStore>>>,
 }
 
-use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::Read;
 use tauri::AppHandle;
 
-// Define a struct to deserialize the YAML into
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Config {
-    app_name: String,
-    version: String,
-    settings: Settings,
-    wallets: Vec<Wallet>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Wallet {
-    name: String,
-    public_key: String,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct Settings {
-    theme: String,
-    debug: bool,
-}
-
 // Tauri command to load the config
 #[tauri::command]
 fn load_config(app: AppHandle) -> Result<Config, String> {
@@ -118,9 +134,20 @@ fn initialize_config(app: AppHandle) {
                 .wallets
                 .first()
                 .map(|wallet| wallet.public_key.clone());
+
+            match Fetcher::from_config(&config.api) {
+                Ok(fetcher) => *app_state.fetcher.lock().unwrap() = fetcher,
+                Err(e) => error!("Invalid api config, using defaults: {}", e),
+            }
+            *app_state.api_config.lock().unwrap() = config.api;
+            *app_state.retention_policy.lock().unwrap() = config.retention;
+            *app_state.monitor_settings.lock().unwrap() = config.monitors;
         }
         Err(e) => {
             dbg!("Failed to load config: {}", e);
+            if let Ok(fetcher) = Fetcher::from_config(&ApiConfig::default()) {
+                *app.state::<AppState>().fetcher.lock().unwrap() = fetcher;
+            }
         }
     }
 }
@@ -155,11 +182,18 @@ pub fn run() {
             let app_handle = app.app_handle();
             initialize_config(app_handle.clone());
 
-            let token_registry = TokenRegistry::new();
+            let mut token_registry = TokenRegistry::new();
+            // Pairs the user flipped last time.
+            let inverted_pairs_path = app.path().app_data_dir()?.join(INVERTED_PAIRS_FILE);
+            match TokenRegistry::load_inverted_pairs(&inverted_pairs_path) {
+                Ok(inverted_pairs) => token_registry.inverted_pairs = inverted_pairs,
+                Err(e) => error!("Failed to load inverted pairs: {:#}", e),
+            }
             let app_state = app.state::<AppState>();
             *app_state.token_registry.lock().unwrap() = token_registry.clone();
 
-            let (tray_id, tray_menu) = setup_tray(app.handle()).expect("Expect tray_id");
+            let (tray_id, tray_menu) =
+                setup_tray(app.handle(), &token_registry).expect("Expect tray_id");
             *app_state.tray_id.lock().unwrap() = Some(tray_id.clone());
             *app_state.tray_menu.lock().unwrap() = Some(tray_menu.clone());
 
@@ -174,6 +208,21 @@ pub fn run() {
             >(Default::default());
             *app_state.price_sender.lock().unwrap() = Some(price_sender.clone());
 
+            // Keep every tick so history survives restarts.
+            let store_path = app.path().app_data_dir()?.join("prices.sqlite");
+            match PriceStore::open(&store_path) {
+                Ok(price_store) => {
+                    let price_store = Arc::new(price_store);
+                    *app_state.price_store.lock().unwrap() = Some(price_store.clone());
+                    let retention_policy = app_state.retention_policy.lock().unwrap().clone();
+                    let price_receiver = price_sender.subscribe();
+                    tauri::async_runtime::spawn(async move {
+                        price_store.run(price_receiver, retention_policy).await;
+                    });
+                }
+                Err(e) => error!("Price history disabled: {:#}", e),
+            }
+
             let app_handle = app.handle().clone();
 
             // Default to SOL
@@ -255,41 +304,93 @@ pub fn run() {
                         .unwrap()
                         .clone();
 
-                    let maybe_price_info =
-                        price_info_map.get(&selected_token_or_pair_address.address);
+                    // Pairs are shown the way the user flipped them.
+                    let token_registry = app_state.token_registry.lock().unwrap().clone();
+                    let maybe_price_info = get_price_info(
+                        &price_info_map,
+                        &token_registry.display_address(&selected_token_or_pair_address.address),
+                    );
+
+                    let perps = price_info_map
+                        .values()
+                        .filter_map(|info| match info {
+                            TokenOrPairPriceInfo::Perp(perp_value_info) => Some(perp_value_info),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+
+                    let degraded =
+                        format_degraded_upstreams(&app_state.fetcher.lock().unwrap().health());
+                    // Outages first, otherwise the selected key's moves and indicators.
+                    let tooltip = degraded.clone().or_else(|| {
+                        let price_info = maybe_price_info.as_ref()?.price_info();
+                        let lines = [
+                            price_info.change.as_ref().and_then(format_changes),
+                            price_info.ta.as_ref().and_then(format_indicators),
+                        ]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>();
+                        (!lines.is_empty()).then(|| lines.join("\n"))
+                    });
+                    let _ = tray_icon.set_tooltip(tooltip.as_deref());
 
-                    if let Some(price_info) = maybe_price_info {
+                    if let Some(price_info) = &maybe_price_info {
                         let (_label, formatted_price) = update_price_display(price_info);
                         println!("_label:{:?}", _label);
-                        let _ = tray_icon.set_title(Some(formatted_price));
+
+                        // Name the failing upstream rather than a bare "…".
+                        let title = match (price_info.price_info().price, degraded) {
+                            (None, Some(degraded)) => degraded,
+                            _ => formatted_price,
+                        };
+                        // Perps close to liquidation are flagged whatever is selected.
+                        let title = match format_liquidation_danger(&perps) {
+                            Some(danger) => format!("{} {}", danger, title),
+                            None => title,
+                        };
+                        let _ = tray_icon.set_title(Some(title));
                     }
 
-                    // Update menu
+                    // Update menu, perps first as markets come and go.
+                    if let Err(e) =
+                        sync_perps_menu_items(&cloned_app_handle, &tray_menu_clone, &perps)
+                    {
+                        warn!("Failed to update perps menu: {}", e);
+                    }
                     let items = tray_menu_clone.items().unwrap();
                     price_info_map.iter().for_each(|(token_address, v)| {
                         match v {
                             TokenOrPairPriceInfo::Perp(perp_value_info) => {
-                                if let Some(item) = items
-                                    .iter()
-                                    // TODO: Use id for single and pair
-                                    .find(|menu_item| {
-                                        menu_item.id().0.as_str() == perp_value_info.id
-                                    })
-                                {
+                                if let Some(item) = items.iter().find(|menu_item| {
+                                    menu_item.id().0.as_str() == perp_value_info.id
+                                }) {
                                     if let Some(item) = item.as_icon_menuitem() {
-                                        let (_label, formatted_price) = update_price_display(v);
-                                        let _ = item.set_text(formatted_price);
+                                        // Name the market, there can be several.
+                                        let (label, formatted_price) = update_price_display(v);
+                                        let _ =
+                                            item.set_text(format!("{} {}", label, formatted_price));
                                     }
                                 }
                             }
                             _ => {
-                                if let Some(item) = items
-                                    .iter()
-                                    .find(|menu_item| menu_item.id().0.as_str() == token_address)
-                                {
+                                let display_address = token_registry.display_address(token_address);
+                                if let (Some(item), Some(v)) = (
+                                    items.iter().find(|menu_item| {
+                                        menu_item.id().0.as_str() == token_address
+                                    }),
+                                    get_price_info(&price_info_map, &display_address),
+                                ) {
                                     if let Some(item) = item.as_icon_menuitem() {
-                                        let (_label, formatted_price) = update_price_display(v);
-                                        let _ = item.set_text(formatted_price);
+                                        let (label, formatted_price) = update_price_display(&v);
+                                        // Pairs name their direction, it can be flipped.
+                                        let text = match v {
+                                            TokenOrPairPriceInfo::Pair(_) => {
+                                                format!("{} {}", label, formatted_price)
+                                            }
+                                            _ => formatted_price,
+                                        };
+                                        let _ = item.set_text(text);
                                     }
                                 }
                             }
@@ -298,6 +399,34 @@ pub fn run() {
                 }
             });
 
+            // Venue divergence, to the frontend and as a notification.
+            let (divergence_sender, mut divergence_receiver) = mpsc::unbounded_channel();
+            let divergence_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                while let Some(event) = divergence_receiver.recv().await {
+                    let _ = divergence_app_handle.emit("divergence", &event);
+                    let _ = divergence_app_handle
+                        .notification()
+                        .builder()
+                        .title(format_divergence_event(&event))
+                        .show();
+                }
+            });
+
+            // Positions close to liquidation, escalating as they get closer.
+            let (liquidation_sender, mut liquidation_receiver) = mpsc::unbounded_channel();
+            let liquidation_app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                while let Some(alert) = liquidation_receiver.recv().await {
+                    let _ = liquidation_app_handle.emit("liquidation", &alert);
+                    let _ = liquidation_app_handle
+                        .notification()
+                        .builder()
+                        .title(format_liquidation_alert(&alert))
+                        .show();
+                }
+            });
+
             // // Notify
             // app.notification()
             // .builder()
@@ -318,11 +447,24 @@ pub fn run() {
                 .clone()
                 .unwrap();
 
+            let fetcher = app_state.fetcher.lock().unwrap().clone();
+            let api_config = app_state.api_config.lock().unwrap().clone();
+            let monitor_settings = app_state.monitor_settings.lock().unwrap().clone();
+            let price_store = app_state.price_store.lock().unwrap().clone();
+
             tauri::async_runtime::spawn(async move {
                 if let Err(e) = run_loop(
                     price_sender.clone(),
                     &token_registry,
                     Some(maybe_wallet_address.as_str()),
+                    fetcher,
+                    &api_config,
+                    Monitors {
+                        settings: monitor_settings,
+                        divergence_sender,
+                        liquidation_sender,
+                    },
+                    price_store,
                 )
                 .await
                 {
@@ -359,6 +501,18 @@ pub fn run() {
                     window.show().unwrap();
                     window.set_focus().unwrap();
                 }
+                "flip_pair" => {
+                    drop(token_registry);
+                    let selected_address = app_state
+                        .selected_token_or_pair_address
+                        .lock()
+                        .unwrap()
+                        .address
+                        .clone();
+                    if let Err(e) = flip_pair(app_handle.clone(), selected_address) {
+                        warn!("Can't flip: {}", e);
+                    }
+                }
                 "quit" => {
                     *app_handle.state::<AppState>().is_quit.lock().unwrap() = true;
                     app_handle.exit(0);
@@ -405,7 +559,16 @@ pub fn run() {
                 }
             }
         })
-        .invoke_handler(tauri::generate_handler![load_config, greet])
+        .invoke_handler(tauri::generate_handler![
+            load_config,
+            greet,
+            get_upstream_health,
+            get_price_history,
+            get_indicators,
+            get_forecast,
+            flip_pair,
+            get_range_stability
+        ])
         .build(tauri::generate_context!())
         .expect("error while building tauri application");
 
@@ -0,0 +1,239 @@
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    jup::perps::{Position, Side},
+    token_registry::Token,
+};
+
+/// Distances to liquidation, in percent of the mark price, that raise an alert, e.g.
+///
+/// ```yaml
+/// liquidation:
+///   thresholds_pct: [20.0, 10.0, 5.0]
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LiquidationSettings {
+    pub thresholds_pct: Vec<f64>,
+}
+
+impl Default for LiquidationSettings {
+    fn default() -> Self {
+        Self {
+            thresholds_pct: vec![20.0, 10.0, 5.0],
+        }
+    }
+}
+
+/// How far the mark price can move against a position before it is liquidated,
+/// negative once past the liquidation price.
+pub fn distance_to_liquidation_pct(
+    side: &Side,
+    mark_price: f64,
+    liquidation_price: f64,
+) -> Option<f64> {
+    if mark_price <= 0.0 || liquidation_price <= 0.0 {
+        return None;
+    }
+    let distance = match side {
+        Side::Long => mark_price - liquidation_price,
+        Side::Short => liquidation_price - mark_price,
+    };
+    Some(distance / mark_price * 100.0)
+}
+
+/// Closest position of a market to liquidation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LiquidationStatus {
+    pub distance_pct: f64,
+    // Tightest threshold crossed, `None` while the position is safe.
+    pub threshold_pct: Option<f64>,
+}
+
+impl LiquidationStatus {
+    pub fn is_in_danger(&self) -> bool {
+        self.threshold_pct.is_some()
+    }
+}
+
+/// A position that moved past a tighter threshold.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LiquidationAlert {
+    pub position_pubkey: String,
+    pub market: Token,
+    pub side: Side,
+    pub mark_price: f64,
+    pub liquidation_price: f64,
+    pub distance_pct: f64,
+    pub threshold_pct: f64,
+}
+
+/// Watches open positions and alerts each time one gets closer to liquidation than the next threshold.
+pub struct LiquidationMonitor {
+    // Widest first.
+    thresholds_pct: Vec<f64>,
+    // Thresholds crossed by each position, alerts fire only when this goes up.
+    levels: HashMap<String, usize>,
+    // Market address and status of each position from the last check.
+    statuses: HashMap<String, (String, LiquidationStatus)>,
+}
+
+impl LiquidationMonitor {
+    pub fn new(settings: LiquidationSettings) -> Self {
+        let mut thresholds_pct = settings.thresholds_pct;
+        thresholds_pct.sort_by(|a, b| b.total_cmp(a));
+
+        Self {
+            thresholds_pct,
+            levels: HashMap::new(),
+            statuses: HashMap::new(),
+        }
+    }
+
+    /// Checks every open position against the mark price of its market, keyed by mint.
+    /// Closed positions are forgotten, so reopening one alerts again.
+    /// Positions without a mark price this time keep their last level and status.
+    pub fn check(
+        &mut self,
+        positions: &[Position],
+        mark_prices: &HashMap<String, f64>,
+    ) -> Vec<LiquidationAlert> {
+        let mut alerts = Vec::new();
+        let open = positions
+            .iter()
+            .map(|position| position.pubkey.as_str())
+            .collect::<HashSet<_>>();
+        self.levels
+            .retain(|pubkey, _| open.contains(pubkey.as_str()));
+        self.statuses
+            .retain(|pubkey, _| open.contains(pubkey.as_str()));
+
+        for position in positions {
+            let (Some(mark_price), Some(liquidation_price)) = (
+                mark_prices.get(&position.market.address),
+                position.liquidation_price.to_f64(),
+            ) else {
+                continue;
+            };
+            let Some(distance_pct) =
+                distance_to_liquidation_pct(&position.side, *mark_price, liquidation_price)
+            else {
+                continue;
+            };
+
+            let level = self
+                .thresholds_pct
+                .iter()
+                .filter(|threshold_pct| distance_pct <= **threshold_pct)
+                .count();
+            let threshold_pct = level.checked_sub(1).map(|i| self.thresholds_pct[i]);
+            if let Some(threshold_pct) = threshold_pct {
+                if level > self.levels.get(&position.pubkey).copied().unwrap_or(0) {
+                    alerts.push(LiquidationAlert {
+                        position_pubkey: position.pubkey.clone(),
+                        market: position.market.clone(),
+                        side: position.side.clone(),
+                        mark_price: *mark_price,
+                        liquidation_price,
+                        distance_pct,
+                        threshold_pct,
+                    });
+                }
+            }
+
+            self.levels.insert(position.pubkey.clone(), level);
+            self.statuses.insert(
+                position.pubkey.clone(),
+                (
+                    position.market.address.clone(),
+                    LiquidationStatus {
+                        distance_pct,
+                        threshold_pct,
+                    },
+                ),
+            );
+        }
+
+        alerts
+    }
+
+    /// Status of the position closest to liquidation in a market.
+    pub fn status(&self, market_address: &str) -> Option<LiquidationStatus> {
+        self.statuses
+            .values()
+            .filter(|(address, _)| address == market_address)
+            .map(|(_, status)| *status)
+            .min_by(|a, b| a.distance_pct.total_cmp(&b.distance_pct))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fetcher::tests::fixture_fetcher,
+        jup::perps::{PerpsFetcher, PERPS_API_BASE},
+        token_registry::TokenRegistry,
+    };
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+
+    #[test]
+    fn test_distance_to_liquidation() {
+        let long = distance_to_liquidation_pct(&Side::Long, 100.0, 80.0).unwrap();
+        assert!((long - 20.0).abs() < 1e-9);
+        let short = distance_to_liquidation_pct(&Side::Short, 100.0, 105.0).unwrap();
+        assert!((short - 5.0).abs() < 1e-9);
+        // Past liquidation.
+        assert!(distance_to_liquidation_pct(&Side::Long, 100.0, 101.0).unwrap() < 0.0);
+        assert_eq!(distance_to_liquidation_pct(&Side::Long, 0.0, 80.0), None);
+    }
+
+    #[tokio::test]
+    async fn test_alerts_escalate() {
+        // Long SOL, liquidated at 109.46.
+        let positions = PerpsFetcher::with_fetcher(fixture_fetcher(), PERPS_API_BASE)
+            .fetch_open_positions(
+                "7fgGqjCqvSbDLqEfrcWKyd8VWYuyGZ9bD2jjJoSu9jDm",
+                &TokenRegistry::new(),
+            )
+            .await
+            .unwrap();
+        let mut monitor = LiquidationMonitor::new(LiquidationSettings::default());
+        let mut check = |mark_price: f64| {
+            let mark_prices = HashMap::from([(SOL.to_owned(), mark_price)]);
+            monitor
+                .check(&positions, &mark_prices)
+                .iter()
+                .map(|alert| alert.threshold_pct)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(check(168.95), Vec::<f64>::new());
+        // Straight past 20% and 10%, one alert at the tighter one.
+        assert_eq!(check(120.0), vec![10.0]);
+        assert_eq!(check(115.0), vec![5.0]);
+        // Easing off is quiet, crossing 5% again alerts again.
+        assert_eq!(check(118.0), Vec::<f64>::new());
+        assert_eq!(check(114.0), vec![5.0]);
+
+        let status = monitor.status(SOL).unwrap();
+        assert!((status.distance_pct - (114.0 - 109.46) / 114.0 * 100.0).abs() < 1e-9);
+        assert!(status.is_in_danger());
+
+        // A missing mark price changes nothing, the next one doesn't alert again.
+        assert_eq!(monitor.check(&positions, &HashMap::new()), vec![]);
+        assert!(monitor.status(SOL).unwrap().is_in_danger());
+        assert_eq!(
+            monitor.check(&positions, &HashMap::from([(SOL.to_owned(), 114.0)])),
+            vec![]
+        );
+        assert!(monitor.status(SOL).unwrap().is_in_danger());
+
+        // Closed positions have no status.
+        monitor.check(&[], &HashMap::new());
+        assert_eq!(monitor.status(SOL), None);
+    }
+}
@@ -0,0 +1,492 @@
+use anyhow::{anyhow, ensure, Result};
+use async_trait::async_trait;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio::time::Duration;
+
+use crate::{
+    feeder::{
+        OraclePriceInfo, PairPriceInfo, PriceInfo, TokenOrPairAddress, TokenOrPairPriceInfo,
+        TokenPriceInfo,
+    },
+    formatter::format_price,
+    solana::SolanaRpc,
+    source::{PriceSource, PriceSourceKind},
+    time::get_unix_timestamp,
+    token_registry::{get_pair_ot_token_address_from_tokens, Token},
+};
+
+// sha256("account:PriceUpdateV2")[..8]
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+// Discriminator and write authority.
+const VERIFICATION_LEVEL: usize = 40;
+const PRICE_MESSAGE_LEN: usize = 84;
+
+/// Key of the oracle price of a token, e.g. `So11111111111111111111111111111111111111112_PYTH`.
+pub fn oracle_key(address: &str) -> TokenOrPairAddress {
+    format!("{address}_PYTH")
+}
+
+/// A decoded Pyth `PriceUpdateV2` account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceUpdate {
+    pub feed_id: String,
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    // Unix seconds.
+    pub publish_time: i64,
+    pub ema_price: i64,
+    pub ema_conf: u64,
+    // Verified by the full guardian set, partial updates are not trusted.
+    pub is_fully_verified: bool,
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    data[offset..offset + N].try_into().expect("Length checked")
+}
+
+impl PriceUpdate {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        ensure!(
+            data.len() > VERIFICATION_LEVEL && data[..8] == PRICE_UPDATE_V2_DISCRIMINATOR,
+            "Not a Pyth price update account"
+        );
+
+        // Borsh enum, `Partial { num_signatures: u8 }` or `Full`.
+        let (is_fully_verified, offset) = match data[VERIFICATION_LEVEL] {
+            0 => (false, VERIFICATION_LEVEL + 2),
+            1 => (true, VERIFICATION_LEVEL + 1),
+            level => return Err(anyhow!("Unknown verification level {level}")),
+        };
+        ensure!(
+            data.len() >= offset + PRICE_MESSAGE_LEN,
+            "Pyth price update too short: {} bytes",
+            data.len()
+        );
+
+        let feed_id = data[offset..offset + 32]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let offset = offset + 32;
+
+        Ok(Self {
+            feed_id,
+            price: i64::from_le_bytes(read_bytes(data, offset)),
+            conf: u64::from_le_bytes(read_bytes(data, offset + 8)),
+            exponent: i32::from_le_bytes(read_bytes(data, offset + 16)),
+            publish_time: i64::from_le_bytes(read_bytes(data, offset + 20)),
+            // Skips `prev_publish_time`.
+            ema_price: i64::from_le_bytes(read_bytes(data, offset + 36)),
+            ema_conf: u64::from_le_bytes(read_bytes(data, offset + 44)),
+            is_fully_verified,
+        })
+    }
+
+    fn scale(&self, value: f64) -> f64 {
+        value * 10f64.powi(self.exponent)
+    }
+
+    pub fn price(&self) -> f64 {
+        self.scale(self.price as f64)
+    }
+
+    pub fn confidence(&self) -> f64 {
+        self.scale(self.conf as f64)
+    }
+}
+
+/// Oracle price with its confidence interval, both in USD.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OraclePrice {
+    pub price: f64,
+    pub confidence: f64,
+    // Unix seconds.
+    pub publish_time: u64,
+}
+
+impl OraclePrice {
+    fn price_info(&self) -> PriceInfo {
+        PriceInfo {
+            price: Some(self.price),
+            formatted_price: format_price(self.price),
+            updated_at: self.publish_time,
+            source: Some(PriceSourceKind::Pyth),
+            confidence: Some(self.confidence),
+            ..Default::default()
+        }
+    }
+
+    /// `self` priced in `quote`, relative confidences add up.
+    fn ratio(&self, quote: &OraclePrice) -> Option<OraclePrice> {
+        if quote.price == 0.0 || self.price == 0.0 {
+            return None;
+        }
+
+        let price = self.price / quote.price;
+        let relative_confidence = self.confidence / self.price + quote.confidence / quote.price;
+        Some(OraclePrice {
+            price,
+            confidence: price * relative_confidence,
+            publish_time: self.publish_time.min(quote.publish_time),
+        })
+    }
+}
+
+/// Reads Pyth price accounts over Solana RPC.
+#[derive(Clone)]
+pub struct PythFetcher {
+    rpc: SolanaRpc,
+    // Token mint to Pyth price account.
+    price_accounts: HashMap<String, String>,
+    max_age: Duration,
+    // Last fetched price of each token, shared by clones.
+    latest_prices: Arc<Mutex<HashMap<String, Result<OraclePrice, String>>>>,
+}
+
+impl PythFetcher {
+    pub fn new(rpc: SolanaRpc, price_accounts: HashMap<String, String>) -> Self {
+        Self {
+            rpc,
+            price_accounts,
+            max_age: Duration::from_secs(60),
+            latest_prices: Arc::default(),
+        }
+    }
+
+    /// Older updates are treated as missing.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn has_feed(&self, address: &str) -> bool {
+        self.price_accounts.contains_key(address)
+    }
+
+    /// Last fetched prices of the given tokens, without a request.
+    /// Prices that aged past `max_age` since are left out.
+    pub fn latest_prices(&self, tokens: &[Token]) -> HashMap<String, OraclePrice> {
+        let now = get_unix_timestamp();
+        let latest_prices = self.latest_prices.lock().unwrap();
+        tokens
+            .iter()
+            .filter_map(|token| {
+                let price = latest_prices.get(&token.address)?.clone().ok()?;
+                (now.saturating_sub(price.publish_time) <= self.max_age.as_secs())
+                    .then(|| (token.address.clone(), price))
+            })
+            .collect()
+    }
+
+    /// Prices tokens with a known feed in one RPC call, each entry fails on its own.
+    pub async fn fetch_prices(
+        &self,
+        tokens: &[Token],
+    ) -> Result<HashMap<String, Result<OraclePrice, String>>> {
+        let tokens = tokens
+            .iter()
+            .filter(|token| self.has_feed(&token.address))
+            .collect::<Vec<_>>();
+        if tokens.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let accounts = tokens
+            .iter()
+            .map(|token| self.price_accounts[&token.address].as_str())
+            .collect::<Vec<_>>();
+        let datas = self.rpc.get_multiple_accounts(&accounts).await?;
+        let now = get_unix_timestamp();
+
+        let prices: HashMap<_, _> = tokens
+            .into_iter()
+            .zip(datas)
+            .map(|(token, data)| {
+                let price = data
+                    .ok_or_else(|| format!("Pyth account for {} not found", token.symbol))
+                    .and_then(|data| PriceUpdate::decode(&data).map_err(|e| e.to_string()))
+                    .and_then(|update| {
+                        let publish_time = update.publish_time.max(0) as u64;
+                        if !update.is_fully_verified {
+                            Err(format!(
+                                "Pyth {} update is partially verified",
+                                token.symbol
+                            ))
+                        } else if now.saturating_sub(publish_time) > self.max_age.as_secs() {
+                            Err(format!("Pyth {} price is stale", token.symbol))
+                        } else {
+                            Ok(OraclePrice {
+                                price: update.price(),
+                                confidence: update.confidence(),
+                                publish_time,
+                            })
+                        }
+                    });
+                (token.address.clone(), price)
+            })
+            .collect();
+        self.latest_prices.lock().unwrap().extend(prices.clone());
+        Ok(prices)
+    }
+
+    /// Oracle view of the given tokens, keyed by `oracle_key`.
+    pub async fn fetch_oracle_prices(
+        &self,
+        tokens: &[Token],
+    ) -> HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> {
+        let prices = match self.fetch_prices(tokens).await {
+            Ok(prices) => prices,
+            Err(e) => tokens
+                .iter()
+                .filter(|token| self.has_feed(&token.address))
+                .map(|token| (token.address.clone(), Err(e.to_string())))
+                .collect(),
+        };
+
+        tokens
+            .iter()
+            .filter_map(|token| {
+                let price_info = match prices.get(&token.address)? {
+                    Ok(price) => price.price_info(),
+                    Err(e) => PriceInfo::failed(e),
+                };
+                let id = oracle_key(&token.address);
+                Some((
+                    id.clone(),
+                    TokenOrPairPriceInfo::Oracle(OraclePriceInfo {
+                        id,
+                        token: token.clone(),
+                        price_info,
+                    }),
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Oracle backed price source, tried after the DEX sources.
+/// Answers from the prices the runner fetched this tick rather than asking RPC again.
+pub struct PythPriceSource {
+    fetcher: PythFetcher,
+}
+
+impl PythPriceSource {
+    pub fn new(fetcher: PythFetcher) -> Self {
+        Self { fetcher }
+    }
+}
+
+#[async_trait]
+impl PriceSource for PythPriceSource {
+    fn kind(&self) -> PriceSourceKind {
+        PriceSourceKind::Pyth
+    }
+
+    fn priority(&self) -> u8 {
+        2
+    }
+
+    async fn fetch_prices(
+        &self,
+        single_tokens: &[Token],
+        pairs: &[[Token; 2]],
+    ) -> Result<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>> {
+        let mut tokens = single_tokens.to_vec();
+        tokens.extend(pairs.iter().flatten().cloned());
+        let mut seen = HashSet::new();
+        tokens.retain(|token| seen.insert(token.address.clone()));
+        let prices = self.fetcher.latest_prices(&tokens);
+        let price_of = |token: &Token| prices.get(&token.address).copied();
+
+        let mut all_prices: HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> = HashMap::new();
+        for token in single_tokens {
+            if let Some(price) = price_of(token) {
+                all_prices.insert(
+                    token.address.clone(),
+                    TokenOrPairPriceInfo::Token(TokenPriceInfo {
+                        token: token.clone(),
+                        price_info: price.price_info(),
+                    }),
+                );
+            }
+        }
+
+        for pair in pairs {
+            let [token_a, token_b] = pair;
+            let Some(price) = price_of(token_a)
+                .zip(price_of(token_b))
+                .and_then(|(a, b)| a.ratio(&b))
+            else {
+                continue;
+            };
+
+            all_prices.insert(
+                get_pair_ot_token_address_from_tokens(pair)?,
+                TokenOrPairPriceInfo::Pair(PairPriceInfo {
+                    token_a: token_a.clone(),
+                    token_b: token_b.clone(),
+                    price_info: price.price_info(),
+                }),
+            );
+        }
+
+        if all_prices.is_empty() {
+            return Err(anyhow!("Pyth has no price for the requested tokens"));
+        }
+
+        Ok(all_prices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fetcher::tests::fixture_fetcher, jup::prices::TokenSymbol, solana::SOLANA_RPC,
+        token_registry::TokenRegistry,
+    };
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+    const JUP: &str = "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN";
+
+    /// `PriceUpdateV2` account with a `Full` or `Partial` verification level.
+    fn price_update(price: i64, conf: u64, publish_time: i64, is_full: bool) -> Vec<u8> {
+        let mut data = PRICE_UPDATE_V2_DISCRIMINATOR.to_vec();
+        data.extend([7u8; 32]);
+        match is_full {
+            true => data.push(1),
+            false => data.extend([0, 5]),
+        }
+        data.extend([0xefu8; 32]);
+        data.extend(price.to_le_bytes());
+        data.extend(conf.to_le_bytes());
+        data.extend((-8i32).to_le_bytes());
+        data.extend(publish_time.to_le_bytes());
+        data.extend((publish_time - 1).to_le_bytes());
+        data.extend((price + 100).to_le_bytes());
+        data.extend((conf * 2).to_le_bytes());
+        data.extend(321_876_543u64.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_decode_price_update() {
+        let update = PriceUpdate::decode(&price_update(
+            17_264_000_000,
+            8_500_000,
+            1_739_600_000,
+            true,
+        ))
+        .unwrap();
+
+        assert!(update.is_fully_verified);
+        assert_eq!(update.feed_id, "ef".repeat(32));
+        assert_eq!(update.publish_time, 1_739_600_000);
+        assert_eq!(update.ema_price, 17_264_000_100);
+        assert_eq!(update.ema_conf, 17_000_000);
+        assert!((update.price() - 172.64).abs() < 1e-9);
+        assert!((update.confidence() - 0.085).abs() < 1e-12);
+
+        // Partial verification shifts the message by one byte.
+        let partial = PriceUpdate::decode(&price_update(1, 2, 3, false)).unwrap();
+        assert!(!partial.is_fully_verified);
+        assert_eq!(
+            (partial.price, partial.conf, partial.publish_time),
+            (1, 2, 3)
+        );
+
+        assert!(PriceUpdate::decode(&[0u8; 200]).is_err());
+    }
+
+    #[test]
+    fn test_ratio_adds_relative_confidence() {
+        let sol = OraclePrice {
+            price: 200.0,
+            confidence: 2.0,
+            publish_time: 10,
+        };
+        let jup = OraclePrice {
+            price: 1.0,
+            confidence: 0.02,
+            publish_time: 9,
+        };
+
+        let ratio = jup.ratio(&sol).unwrap();
+        assert_eq!(ratio.price, 0.005);
+        assert!((ratio.confidence - 0.005 * 0.03).abs() < 1e-12);
+        assert_eq!(ratio.publish_time, 9);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_prices_replayed() {
+        let fetcher = PythFetcher::new(
+            SolanaRpc::with_fetcher(fixture_fetcher(), SOLANA_RPC),
+            HashMap::from([
+                (
+                    SOL.to_owned(),
+                    "7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE".to_owned(),
+                ),
+                (
+                    JUP.to_owned(),
+                    "7dbob1psH1iZBS7qPsm3Kwbf5DzSXK8Jyg31CTgTnxH5".to_owned(),
+                ),
+            ]),
+        )
+        // The fixture is as old as its recording.
+        .with_max_age(Duration::from_secs(u64::MAX / 2));
+        let token_registry = TokenRegistry::new();
+        let sol = token_registry
+            .get_by_symbol(&TokenSymbol::SOL)
+            .unwrap()
+            .clone();
+        let jup = token_registry
+            .get_by_symbol(&TokenSymbol::JUP)
+            .unwrap()
+            .clone();
+
+        let prices = fetcher
+            .fetch_prices(&[sol.clone(), jup.clone()])
+            .await
+            .unwrap();
+        let sol_price = prices[SOL].clone().unwrap();
+        assert!((sol_price.price - 172.64).abs() < 1e-9);
+        assert!((sol_price.confidence - 0.085).abs() < 1e-9);
+        assert_eq!(
+            prices[JUP].clone().unwrap_err(),
+            "Pyth JUP update is partially verified"
+        );
+
+        let oracle_prices = fetcher
+            .fetch_oracle_prices(&[sol.clone(), jup.clone()])
+            .await;
+        let TokenOrPairPriceInfo::Oracle(sol_info) = &oracle_prices[&oracle_key(SOL)] else {
+            panic!("Not an oracle price");
+        };
+        assert_eq!(sol_info.id, oracle_key(SOL));
+        assert_eq!(sol_info.token, sol);
+        assert_eq!(sol_info.price_info.source, Some(PriceSourceKind::Pyth));
+        assert_eq!(sol_info.price_info.confidence, Some(sol_price.confidence));
+        assert!(!oracle_prices[&oracle_key(JUP)].is_ok());
+
+        // The fallback source reads what was fetched, no second request.
+        let source_prices = PythPriceSource::new(fetcher.clone())
+            .fetch_prices(&[sol.clone(), jup.clone()], &[])
+            .await
+            .unwrap();
+        assert_eq!(source_prices[SOL].price_info().price, Some(sol_price.price));
+        assert!(!source_prices.contains_key(JUP));
+
+        let unfetched = PythFetcher::new(
+            SolanaRpc::with_fetcher(fixture_fetcher(), SOLANA_RPC),
+            HashMap::new(),
+        );
+        assert!(PythPriceSource::new(unfetched)
+            .fetch_prices(&[sol], &[])
+            .await
+            .is_err());
+    }
+}
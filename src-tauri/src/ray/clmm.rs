@@ -0,0 +1,244 @@
+use anyhow::{anyhow, ensure, Result};
+use strum::IntoEnumIterator;
+
+use crate::{
+    feeder::{PairPriceInfo, PriceInfo, TokenOrPairPriceInfo},
+    formatter::format_price,
+    ray::PoolId,
+    solana::SolanaRpc,
+    source::PriceSourceKind,
+    stream::AccountDecoder,
+    time::get_unix_timestamp,
+    token_registry::Token,
+};
+
+// Byte offsets into the `PoolState` account, after the 8 bytes anchor discriminator.
+const TOKEN_MINT_0: usize = 73;
+const TOKEN_MINT_1: usize = 105;
+const TOKEN_VAULT_0: usize = 137;
+const TOKEN_VAULT_1: usize = 169;
+const MINT_DECIMALS_0: usize = 233;
+const MINT_DECIMALS_1: usize = 234;
+const TICK_SPACING: usize = 235;
+const LIQUIDITY: usize = 237;
+const SQRT_PRICE_X64: usize = 253;
+const TICK_CURRENT: usize = 269;
+const MIN_POOL_STATE_LEN: usize = 273;
+
+/// The part of a Raydium CLMM `PoolState` account needed for pricing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolState {
+    pub token_mint_0: String,
+    pub token_mint_1: String,
+    pub token_vault_0: String,
+    pub token_vault_1: String,
+    pub mint_decimals_0: u8,
+    pub mint_decimals_1: u8,
+    pub tick_spacing: u16,
+    pub liquidity: u128,
+    // Q64.64 square root of the raw token 1 per token 0 price.
+    pub sqrt_price_x64: u128,
+    pub tick_current: i32,
+}
+
+fn read_bytes<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    data[offset..offset + N].try_into().expect("Length checked")
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> String {
+    bs58::encode(&data[offset..offset + 32]).into_string()
+}
+
+impl PoolState {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        ensure!(
+            data.len() >= MIN_POOL_STATE_LEN,
+            "Pool account too short: {} bytes",
+            data.len()
+        );
+
+        Ok(Self {
+            token_mint_0: read_pubkey(data, TOKEN_MINT_0),
+            token_mint_1: read_pubkey(data, TOKEN_MINT_1),
+            token_vault_0: read_pubkey(data, TOKEN_VAULT_0),
+            token_vault_1: read_pubkey(data, TOKEN_VAULT_1),
+            mint_decimals_0: data[MINT_DECIMALS_0],
+            mint_decimals_1: data[MINT_DECIMALS_1],
+            tick_spacing: u16::from_le_bytes(read_bytes(data, TICK_SPACING)),
+            liquidity: u128::from_le_bytes(read_bytes(data, LIQUIDITY)),
+            sqrt_price_x64: u128::from_le_bytes(read_bytes(data, SQRT_PRICE_X64)),
+            tick_current: i32::from_le_bytes(read_bytes(data, TICK_CURRENT)),
+        })
+    }
+
+    /// Price of token 0 in token 1, adjusted by the given mint decimals.
+    pub fn price(&self, decimals_0: u8, decimals_1: u8) -> f64 {
+        let sqrt_price = self.sqrt_price_x64 as f64 / 2f64.powi(64);
+        sqrt_price * sqrt_price * 10f64.powi(decimals_0 as i32 - decimals_1 as i32)
+    }
+
+    /// Price of `base` in `quote` if this pool trades that pair in either direction.
+    pub fn price_of(&self, base: &Token, quote: &Token) -> Option<f64> {
+        if self.token_mint_0 == base.address && self.token_mint_1 == quote.address {
+            Some(self.price(base.decimals, quote.decimals))
+        } else if self.token_mint_0 == quote.address && self.token_mint_1 == base.address {
+            let price = self.price(quote.decimals, base.decimals);
+            (price != 0.0).then(|| 1.0 / price)
+        } else {
+            None
+        }
+    }
+}
+
+/// Known CLMM pool trading the pair, in either direction.
+pub fn find_pool(base: &Token, quote: &Token) -> Option<PoolId> {
+    PoolId::iter().find(|id| {
+        let [mint_0, mint_1] = id.mints();
+        (mint_0 == base.address && mint_1 == quote.address)
+            || (mint_0 == quote.address && mint_1 == base.address)
+    })
+}
+
+/// Reads CLMM pools straight from the chain instead of the Raydium API.
+#[derive(Clone, Default)]
+pub struct ClmmFetcher {
+    rpc: SolanaRpc,
+}
+
+impl ClmmFetcher {
+    pub fn new(rpc: SolanaRpc) -> Self {
+        Self { rpc }
+    }
+
+    pub async fn fetch_pool_state(&self, id: &PoolId) -> Result<PoolState> {
+        let data = self.rpc.get_account_info(&id.to_string()).await?;
+        PoolState::decode(&data)
+    }
+
+    pub async fn fetch_pool_price(&self, id: &PoolId, base: &Token, quote: &Token) -> Result<f64> {
+        self.fetch_pool_state(id)
+            .await?
+            .price_of(base, quote)
+            .ok_or_else(|| anyhow!("Pool {id} does not trade {}/{}", base.symbol, quote.symbol))
+    }
+}
+
+/// Prices a pair from streamed pool account updates.
+pub struct ClmmPoolDecoder {
+    pub base: Token,
+    pub quote: Token,
+}
+
+impl AccountDecoder for ClmmPoolDecoder {
+    fn decode(&self, data: &[u8]) -> Result<TokenOrPairPriceInfo> {
+        let price = PoolState::decode(data)?
+            .price_of(&self.base, &self.quote)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Pool does not trade {}/{}",
+                    self.base.symbol,
+                    self.quote.symbol
+                )
+            })?;
+
+        Ok(TokenOrPairPriceInfo::Pair(PairPriceInfo {
+            token_a: self.base.clone(),
+            token_b: self.quote.clone(),
+            price_info: PriceInfo {
+                price: Some(price),
+                formatted_price: format_price(price),
+                updated_at: get_unix_timestamp(),
+                source: Some(PriceSourceKind::Raydium),
+                ..Default::default()
+            },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fetcher::tests::fixture_fetcher, jup::prices::TokenSymbol, solana::SOLANA_RPC};
+
+    fn sol() -> Token {
+        Token {
+            address: "So11111111111111111111111111111111111111112".to_owned(),
+            symbol: TokenSymbol::SOL,
+            name: "Wrapped SOL".to_owned(),
+            decimals: 9,
+        }
+    }
+
+    fn jlp() -> Token {
+        Token {
+            address: "27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4".to_owned(),
+            symbol: TokenSymbol::JLP,
+            name: "Jupiter Perps LP".to_owned(),
+            decimals: 6,
+        }
+    }
+
+    /// SOL/JLP pool account where 1 SOL is worth `price` JLP.
+    fn pool_account(price: f64) -> Vec<u8> {
+        let mut data = vec![0u8; 1544];
+        let mut put =
+            |offset: usize, bytes: &[u8]| data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        put(
+            TOKEN_MINT_0,
+            &bs58::decode(sol().address).into_vec().unwrap(),
+        );
+        put(
+            TOKEN_MINT_1,
+            &bs58::decode(jlp().address).into_vec().unwrap(),
+        );
+        put(TOKEN_VAULT_0, &[1u8; 32]);
+        put(TOKEN_VAULT_1, &[2u8; 32]);
+        put(MINT_DECIMALS_0, &[9]);
+        put(MINT_DECIMALS_1, &[6]);
+        put(TICK_SPACING, &1u16.to_le_bytes());
+        put(LIQUIDITY, &123_456_789u128.to_le_bytes());
+        let sqrt_price_x64 = ((price / 1_000.0).sqrt() * 2f64.powi(64)) as u128;
+        put(SQRT_PRICE_X64, &sqrt_price_x64.to_le_bytes());
+        put(TICK_CURRENT, &(-32177i32).to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_decode_pool_state() {
+        let state = PoolState::decode(&pool_account(40.0556)).unwrap();
+
+        assert_eq!(state.token_mint_0, sol().address);
+        assert_eq!(state.token_mint_1, jlp().address);
+        assert_eq!(state.token_vault_0, bs58::encode([1u8; 32]).into_string());
+        assert_eq!((state.mint_decimals_0, state.mint_decimals_1), (9, 6));
+        assert_eq!(state.tick_spacing, 1);
+        assert_eq!(state.liquidity, 123_456_789);
+        assert_eq!(state.tick_current, -32177);
+
+        let sol_in_jlp = state.price_of(&sol(), &jlp()).unwrap();
+        let jlp_in_sol = state.price_of(&jlp(), &sol()).unwrap();
+        assert!((sol_in_jlp - 40.0556).abs() < 1e-9);
+        assert!((jlp_in_sol - 1.0 / 40.0556).abs() < 1e-12);
+        assert_eq!(state.price_of(&sol(), &sol()), None);
+
+        assert!(PoolState::decode(&[0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn test_find_pool() {
+        assert!(matches!(find_pool(&jlp(), &sol()), Some(PoolId::SOL_JLP)));
+        assert!(find_pool(&sol(), &sol()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_pool_price_replayed() {
+        let fetcher = ClmmFetcher::new(SolanaRpc::with_fetcher(fixture_fetcher(), SOLANA_RPC));
+
+        let price = fetcher
+            .fetch_pool_price(&PoolId::SOL_JLP, &jlp(), &sol())
+            .await
+            .unwrap();
+
+        assert!((price - 1.0 / 40.0556).abs() < 1e-9);
+    }
+}
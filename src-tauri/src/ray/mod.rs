@@ -0,0 +1,339 @@
+pub mod clmm;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::{
+    feeder::{PairPriceInfo, PriceInfo, TokenOrPairAddress, TokenOrPairPriceInfo, TokenPriceInfo},
+    fetcher::Fetcher,
+    formatter::format_price,
+    source::{PriceSource, PriceSourceKind},
+    time::get_unix_timestamp,
+    token_registry::{get_pair_ot_token_address_from_tokens, Token},
+};
+
+#[derive(EnumString, EnumIter, Display, Debug, Clone)]
+pub enum PoolId {
+    #[strum(to_string = "3d8ksMPuLpaQAUbuRr74tmovmyFFXgAsC3iE5NhsgvnH")]
+    #[allow(non_camel_case_types)]
+    SOL_JLP,
+}
+
+impl PoolId {
+    /// Mint addresses of the pool, in pool order.
+    pub fn mints(&self) -> [&'static str; 2] {
+        match self {
+            PoolId::SOL_JLP => [
+                "So11111111111111111111111111111111111111112",
+                "27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4",
+            ],
+        }
+    }
+}
+
+pub const RAYDIUM_BASE_API: &str = "https://api-v3.raydium.io";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolInfoResponse {
+    pub id: String,
+    pub success: bool,
+    pub data: Vec<PoolData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolData {
+    pub r#type: String,
+    pub program_id: String,
+    pub id: String,
+    pub mint_a: Mint,
+    pub mint_b: Mint,
+    pub reward_default_pool_infos: String,
+    pub reward_default_infos: Vec<String>,
+    pub price: f64,
+    pub mint_amount_a: f64,
+    pub mint_amount_b: f64,
+    pub fee_rate: f64,
+    pub open_time: String,
+    pub tvl: f64,
+    pub day: TimeFrameData,
+    pub week: TimeFrameData,
+    pub month: TimeFrameData,
+    pub pooltype: Vec<String>,
+    pub farm_upcoming_count: u32,
+    pub farm_ongoing_count: u32,
+    pub farm_finished_count: u32,
+    pub config: Config,
+    pub burn_percent: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Mint {
+    pub chain_id: u32,
+    pub address: String,
+    pub program_id: String,
+    #[serde(rename = "logoURI")]
+    pub logo_uri: String,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+    pub tags: Vec<String>,
+    pub extensions: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeFrameData {
+    pub volume: f64,
+    pub volume_quote: f64,
+    pub volume_fee: f64,
+    pub apr: f64,
+    pub fee_apr: f64,
+    pub price_min: f64,
+    pub price_max: f64,
+    pub reward_apr: Vec<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MintPriceResponse {
+    pub id: String,
+    pub success: bool,
+    // Prices come back as strings, unknown mints as null.
+    pub data: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    pub id: String,
+    pub index: u32,
+    pub protocol_fee_rate: u32,
+    pub trade_fee_rate: u32,
+    pub tick_spacing: u32,
+    pub fund_fee_rate: u32,
+    pub default_range: f64,
+    pub default_range_point: Vec<f64>,
+}
+
+/// Raydium REST API client.
+#[derive(Clone)]
+pub struct RaydiumFetcher {
+    fetcher: Fetcher,
+    base_url: String,
+}
+
+impl Default for RaydiumFetcher {
+    fn default() -> Self {
+        Self::with_fetcher(Fetcher::new(), RAYDIUM_BASE_API)
+    }
+}
+
+impl RaydiumFetcher {
+    pub fn with_fetcher(fetcher: Fetcher, base_url: &str) -> Self {
+        Self {
+            fetcher,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    async fn fetch_pool_info(&self, ids: &str) -> anyhow::Result<PoolInfoResponse> {
+        let url = format!("{}/pools/info/ids?ids={ids}", self.base_url);
+        let json = self
+            .fetcher
+            .fetch_with_retry(&url, |response: PoolInfoResponse| Ok(response))
+            .await?;
+
+        Ok(json)
+    }
+
+    pub async fn fetch_pool_info_by_id(&self, id: PoolId) -> anyhow::Result<PoolData> {
+        let pool_info = self.fetch_pool_info(&id.to_string()).await?;
+
+        pool_info
+            .data
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Pool {id} not found"))
+    }
+
+    pub async fn fetch_pool_infos_by_ids(&self, ids: &[PoolId]) -> anyhow::Result<Vec<PoolData>> {
+        let ids = ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let pool_info = self.fetch_pool_info(&ids.join(",")).await?;
+
+        Ok(pool_info.data)
+    }
+
+    pub async fn fetch_mint_prices(
+        &self,
+        addresses: &[&str],
+    ) -> anyhow::Result<HashMap<String, f64>> {
+        let url = format!("{}/mint/price?mints={}", self.base_url, addresses.join(","));
+        let response = self
+            .fetcher
+            .fetch_with_retry(&url, |response: MintPriceResponse| Ok(response))
+            .await?;
+
+        let prices = response
+            .data
+            .into_iter()
+            .filter_map(|(address, value)| {
+                let price = match value {
+                    serde_json::Value::String(price) => price.parse::<f64>().ok(),
+                    value => value.as_f64(),
+                };
+                price.map(|price| (address, price))
+            })
+            .collect();
+
+        Ok(prices)
+    }
+}
+
+impl PoolData {
+    /// Price of `base` in `quote` if this pool trades that pair in either direction.
+    pub fn price_of(&self, base: &str, quote: &str) -> Option<f64> {
+        if self.mint_a.address == base && self.mint_b.address == quote {
+            Some(self.price)
+        } else if self.mint_a.address == quote && self.mint_b.address == base && self.price != 0.0 {
+            Some(1.0 / self.price)
+        } else {
+            None
+        }
+    }
+}
+
+/// Raydium backed price source, pairs are priced from the known CLMM pools.
+pub struct RaydiumPriceSource {
+    fetcher: RaydiumFetcher,
+    pools: Vec<PoolId>,
+}
+
+impl Default for RaydiumPriceSource {
+    fn default() -> Self {
+        Self::new(RaydiumFetcher::default())
+    }
+}
+
+impl RaydiumPriceSource {
+    pub fn new(fetcher: RaydiumFetcher) -> Self {
+        Self {
+            fetcher,
+            pools: vec![PoolId::SOL_JLP],
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for RaydiumPriceSource {
+    fn kind(&self) -> PriceSourceKind {
+        PriceSourceKind::Raydium
+    }
+
+    fn priority(&self) -> u8 {
+        1
+    }
+
+    async fn fetch_prices(
+        &self,
+        single_tokens: &[Token],
+        pairs: &[[Token; 2]],
+    ) -> Result<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>> {
+        let mut all_prices: HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> = HashMap::new();
+        let price_info = |price: f64| PriceInfo {
+            price: Some(price),
+            formatted_price: format_price(price),
+            updated_at: get_unix_timestamp(),
+            source: Some(PriceSourceKind::Raydium),
+            ..Default::default()
+        };
+
+        if !single_tokens.is_empty() {
+            let addresses: Vec<&str> = single_tokens.iter().map(|t| t.address.as_str()).collect();
+            let prices = self.fetcher.fetch_mint_prices(&addresses).await?;
+            for token in single_tokens {
+                if let Some(price) = prices.get(&token.address) {
+                    all_prices.insert(
+                        token.address.clone(),
+                        TokenOrPairPriceInfo::Token(TokenPriceInfo {
+                            token: token.clone(),
+                            price_info: price_info(*price),
+                        }),
+                    );
+                }
+            }
+        }
+
+        if !pairs.is_empty() {
+            let pools = self.fetcher.fetch_pool_infos_by_ids(&self.pools).await?;
+            for [token_a, token_b] in pairs {
+                let Some(price) = pools
+                    .iter()
+                    .find_map(|pool| pool.price_of(&token_a.address, &token_b.address))
+                else {
+                    continue;
+                };
+
+                all_prices.insert(
+                    get_pair_ot_token_address_from_tokens(&[token_a.clone(), token_b.clone()])?,
+                    TokenOrPairPriceInfo::Pair(PairPriceInfo {
+                        token_a: token_a.clone(),
+                        token_b: token_b.clone(),
+                        price_info: price_info(price),
+                    }),
+                );
+            }
+        }
+
+        if all_prices.is_empty() {
+            return Err(anyhow!("Raydium has no price for the requested tokens"));
+        }
+
+        Ok(all_prices)
+    }
+}
+
+#[allow(dead_code)]
+pub fn get_token_logo_url_by_mint_address(mint_address: &str) -> String {
+    format!("https://img.raydium.io/icon/{mint_address}.png")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{fetcher::tests::fixture_fetcher, ray};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fetch_pool_info_by_id() {
+        let id = ray::PoolId::SOL_JLP;
+        let pool_info = RaydiumFetcher::with_fetcher(fixture_fetcher(), RAYDIUM_BASE_API)
+            .fetch_pool_info_by_id(id)
+            .await;
+
+        // Result
+        println!("{pool_info:#?}");
+
+        // Get price from pool that match id
+        let price = pool_info.unwrap().price;
+
+        println!("{price:#?}");
+        assert!(price > 0.0);
+    }
+
+    #[test]
+    fn test_get_logo_by_mint_address() {
+        let logo =
+            get_token_logo_url_by_mint_address("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+        assert_eq!(
+            logo,
+            "https://img.raydium.io/icon/EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v.png"
+        )
+    }
+}
@@ -1,25 +1,244 @@
 use anyhow::Result;
-use chrono::Utc;
+use futures::{
+    future::{self, join},
+    stream, StreamExt,
+};
 use log::{info, warn};
 use std::collections::HashMap;
-use tokio::sync::watch;
-use tokio::time::{sleep, Duration};
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep_until, timeout_at, Duration, Instant};
 
-use crate::feeder::{PerpValueInfo, PriceInfo, TokenOrPairAddress, TokenOrPairPriceInfo};
+use crate::candle::{Candle, CandleAggregator, Interval};
+use crate::change::ChangeTracker;
+use crate::config::{ApiConfig, MonitorSettings};
+use crate::divergence::{DivergenceEvent, DivergenceMonitor, VenuePrices};
+use crate::feeder::{
+    keep_last_good_prices, synthesize_pairs, PerpValueInfo, PriceInfo, TokenOrPairAddress,
+    TokenOrPairPriceInfo,
+};
+use crate::fetcher::Fetcher;
 use crate::formatter::format_price;
-use crate::jup::perps::PerpsFetcher;
-use crate::jup::prices::{PriceFetcher, TokenSymbol};
-use crate::token_registry::TokenRegistry;
+use crate::jup::charts::CandleFetcher;
+use crate::jup::perps::{perps_key, PerpsFetcher, PositionPNLs};
+use crate::jup::prices::PriceFetcher;
+use crate::liquidation::{LiquidationAlert, LiquidationMonitor};
+use crate::pyth::PythFetcher;
+use crate::ray::RaydiumFetcher;
+use crate::solana::SolanaRpc;
+use crate::source::{PriceSourceKind, PriceSources};
+use crate::store::PriceStore;
+use crate::stream::{stream_subscriptions, LiveKeys, PriceStream};
+use crate::ta::{attach_indicators, Indicators};
+use crate::time::get_unix_timestamp;
+use crate::token_registry::{get_pair_ot_token_address_from_tokens, Token, TokenRegistry};
 
 const POLL_INTERVAL: Duration = Duration::from_secs(5);
 
+// Indicators run on closed bars of this size.
+const TA_INTERVAL: Interval = Interval::OneMinute;
+
+// History asked from the chart API on start, enough to warm every indicator up.
+const SEED_BARS: u64 = 200;
+
+// Chart requests in flight while seeding.
+const SEED_CONCURRENCY: usize = 4;
+
+/// Fetches the indicator bars from the chart API in the background, a few keys at a time,
+/// so a slow chart API doesn't hold up the first prices.
+fn spawn_seed_candles(
+    candle_fetcher: CandleFetcher,
+    token_registry: &TokenRegistry,
+) -> mpsc::UnboundedReceiver<(TokenOrPairAddress, Vec<Candle>)> {
+    let time_to = get_unix_timestamp();
+    let time_from = time_to - SEED_BARS * TA_INTERVAL.secs();
+    let keys = token_registry
+        .tokens
+        .iter()
+        .map(|token| (token.address.clone(), token.clone(), None))
+        .chain(token_registry.all_pairs().filter_map(|pair| {
+            let [base, quote] = pair;
+            Some((
+                get_pair_ot_token_address_from_tokens(pair).ok()?,
+                base.clone(),
+                Some(quote.clone()),
+            ))
+        }))
+        .collect::<Vec<_>>();
+
+    let (seed_sender, seed_receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        stream::iter(keys)
+            .map(|(key, base, quote)| {
+                let candle_fetcher = &candle_fetcher;
+                async move {
+                    let result = candle_fetcher
+                        .fetch_candles(&base, quote.as_ref(), TA_INTERVAL, time_from, time_to)
+                        .await;
+                    (key, result)
+                }
+            })
+            .buffer_unordered(SEED_CONCURRENCY)
+            .for_each(|(key, result)| {
+                match result {
+                    Ok(history) => {
+                        let _ = seed_sender.send((key, history));
+                    }
+                    Err(e) => warn!("Failed to seed {} candles: {}", key, e),
+                }
+                future::ready(())
+            })
+            .await;
+    });
+
+    seed_receiver
+}
+
+/// What every price goes through before it is shown, polled or streamed.
+struct PricePipeline {
+    synthetic_pairs: Vec<[Token; 2]>,
+    last_good_prices: HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    candles: CandleAggregator,
+    indicators: HashMap<TokenOrPairAddress, Indicators>,
+    seed_receiver: mpsc::UnboundedReceiver<(TokenOrPairAddress, Vec<Candle>)>,
+}
+
+impl PricePipeline {
+    fn new(
+        synthetic_pairs: Vec<[Token; 2]>,
+        seed_receiver: mpsc::UnboundedReceiver<(TokenOrPairAddress, Vec<Candle>)>,
+    ) -> Self {
+        Self {
+            synthetic_pairs,
+            last_good_prices: HashMap::new(),
+            candles: CandleAggregator::default(),
+            indicators: HashMap::new(),
+            seed_receiver,
+        }
+    }
+
+    fn process(&mut self, prices_map: &mut HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>) {
+        keep_last_good_prices(prices_map, &mut self.last_good_prices);
+        // After the fallback, so a stale token makes a stale pair.
+        let synthetic_prices = synthesize_pairs(prices_map, &self.synthetic_pairs);
+        prices_map.extend(synthetic_prices);
+        // Seeded history, whatever arrived since the last tick.
+        while let Ok((key, history)) = self.seed_receiver.try_recv() {
+            self.apply_seeded_candles(&key, &history);
+        }
+        let closed = self.candles.update_prices(prices_map);
+        attach_indicators(prices_map, &closed, TA_INTERVAL, &mut self.indicators);
+    }
+
+    /// Merges seeded bars under the live ones and replays the indicators over them.
+    fn apply_seeded_candles(&mut self, key: &str, history: &[Candle]) {
+        self.candles.seed(key, TA_INTERVAL, history);
+        let mut replayed = Indicators::default();
+        for candle in self
+            .candles
+            .series(key, TA_INTERVAL)
+            .into_iter()
+            .flat_map(|series| series.closed())
+        {
+            replayed.next(candle);
+        }
+        self.indicators.insert(key.to_owned(), replayed);
+    }
+}
+
+/// Waits out the poll interval, publishing streamed prices as they come in.
+async fn wait_streaming(
+    duration: Duration,
+    stream_receiver: &mut mpsc::UnboundedReceiver<(TokenOrPairAddress, TokenOrPairPriceInfo)>,
+    pipeline: &mut PricePipeline,
+    changes: &mut ChangeTracker,
+    price_sender: &watch::Sender<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>>,
+) {
+    let deadline = Instant::now() + duration;
+    while let Ok(Some((key, info))) = timeout_at(deadline, stream_receiver.recv()).await {
+        let mut prices_map = HashMap::from([(key, info)]);
+        pipeline.process(&mut prices_map);
+        changes.attach_changes(&mut prices_map);
+        price_sender.send_modify(|prices| {
+            for (key, mut info) in prices_map {
+                // Spreads are polled, keep the last one.
+                if let Some(previous) = prices.get(&key) {
+                    info.price_info_mut().spread_bps = previous.price_info().spread_bps;
+                }
+                prices.insert(key, info);
+            }
+        });
+    }
+    // No stream, or it stopped.
+    sleep_until(deadline).await;
+}
+
+/// Loads the last day of stored ticks, so daily changes show right after a restart.
+fn seed_changes(
+    price_store: &PriceStore,
+    token_registry: &TokenRegistry,
+    changes: &mut ChangeTracker,
+) {
+    let time_to = get_unix_timestamp();
+    let time_from = time_to - 25 * 60 * 60;
+    let keys = token_registry
+        .tokens
+        .iter()
+        .map(|token| token.address.clone())
+        .chain(
+            token_registry
+                .all_pairs()
+                .filter_map(|pair| get_pair_ot_token_address_from_tokens(pair).ok()),
+        );
+
+    for key in keys {
+        match price_store.query(&key, time_from, time_to) {
+            Ok(ticks) => changes.seed(&key, &ticks),
+            Err(e) => warn!("Failed to seed {} changes: {}", key, e),
+        }
+    }
+}
+
+/// Good, non stale USD prices of the given tokens.
+fn fresh_token_prices(
+    prices: &HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    addresses: &[&str],
+) -> HashMap<String, f64> {
+    addresses
+        .iter()
+        .filter_map(|address| match prices.get(*address)? {
+            TokenOrPairPriceInfo::Token(info)
+                if info.price_info.is_ok() && !info.price_info.is_stale =>
+            {
+                Some((address.to_string(), info.price_info.price?))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// What the loop watches on top of prices, and where it sends what is worth a notification.
+pub struct Monitors {
+    pub settings: MonitorSettings,
+    pub divergence_sender: mpsc::UnboundedSender<DivergenceEvent>,
+    pub liquidation_sender: mpsc::UnboundedSender<LiquidationAlert>,
+}
+
 pub async fn run_loop(
     price_sender: watch::Sender<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>>,
     token_registry: &TokenRegistry,
     maybe_wallet_address: Option<&str>,
+    fetcher: Fetcher,
+    api_config: &ApiConfig,
+    monitors: Monitors,
+    price_store: Option<Arc<PriceStore>>,
 ) -> Result<()> {
     let mut retry_count = 0;
-    let price_fetcher = PriceFetcher::new();
+    let candle_fetcher = CandleFetcher::with_fetcher(fetcher.clone(), &api_config.jup_charts_api);
+    let mut pipeline = PricePipeline::new(
+        token_registry.synthetic_pairs.clone(),
+        spawn_seed_candles(candle_fetcher, token_registry),
+    );
 
     // Single tokens
     let singles_tokens = token_registry.tokens.clone();
@@ -27,35 +246,111 @@ pub async fn run_loop(
     // Pairs
     let pairs = token_registry.pairs.clone();
 
+    // Oracle view, shown next to the market prices and used as the last fallback.
+    let pyth_fetcher = PythFetcher::new(
+        SolanaRpc::with_fetcher(fetcher.clone(), &api_config.solana_rpc_url),
+        token_registry.pyth_price_accounts.clone(),
+    );
+    let price_sources =
+        PriceSources::from_config(&fetcher, api_config, token_registry, pyth_fetcher.clone());
+
+    // Same pairs on both venues, to catch them drifting apart.
+    let venue_prices = VenuePrices::new(
+        PriceFetcher::with_fetcher(fetcher.clone(), &api_config.jup_price_api),
+        RaydiumFetcher::with_fetcher(fetcher.clone(), &api_config.raydium_api),
+    );
+    let mut divergence_monitor = DivergenceMonitor::new(monitors.settings.divergence);
+
+    // Streamed keys are left out of polling while the socket is up.
+    let live_keys = LiveKeys::default();
+    let (stream_sender, mut stream_receiver) = mpsc::unbounded_channel();
+    if let Some(ws_url) = &api_config.solana_ws_url {
+        let subscriptions = stream_subscriptions(token_registry);
+        if !subscriptions.is_empty() {
+            let stream = PriceStream::new(ws_url, subscriptions);
+            tokio::spawn(stream.run(stream_sender, live_keys.clone()));
+        }
+    }
+
     // Preps
-    let perps_fetcher = PerpsFetcher::default();
+    let mark_price_fetcher = PriceFetcher::with_fetcher(fetcher.clone(), &api_config.jup_price_api);
+    let perps_fetcher = PerpsFetcher::with_fetcher(fetcher, &api_config.jup_perps_api);
+    let mut liquidation_monitor = LiquidationMonitor::new(monitors.settings.liquidation);
 
-    // POC SOL Perps
-    let sol_token = token_registry
-        .get_by_symbol(&TokenSymbol::SOL)
-        .expect("Invalid symbol");
+    // Moves of every key, for the trend arrows.
+    let mut changes = ChangeTracker::new(monitors.settings.change);
+    if let Some(price_store) = &price_store {
+        seed_changes(price_store, token_registry, &mut changes);
+    }
 
     loop {
         // Token Prices
-        match price_fetcher
-            .fetch_many_price_and_format(singles_tokens.clone(), pairs.clone())
-            .await
-        {
-            Some(prices_map) => {
-                retry_count = 0;
-                // info!("{:#?}", prices_map);
-                price_sender.send(prices_map)?;
+        let polled_tokens = singles_tokens
+            .iter()
+            .filter(|token| !live_keys.contains(&token.address))
+            .cloned()
+            .collect();
+        let polled_pairs = pairs
+            .iter()
+            .filter(|pair| {
+                get_pair_ot_token_address_from_tokens(*pair)
+                    .map(|address| !live_keys.contains(&address))
+                    .unwrap_or(true)
+            })
+            .cloned()
+            .collect();
+        // Oracle first, the fallback source answers from the same read.
+        let ((oracle_prices, mut prices_map), spreads) = join(
+            async {
+                let oracle_prices = pyth_fetcher.fetch_oracle_prices(&singles_tokens).await;
+                let prices_map = price_sources
+                    .fetch_many_price_and_format(polled_tokens, polled_pairs)
+                    .await;
+                (oracle_prices, prices_map)
+            },
+            venue_prices.fetch_spreads(&pairs),
+        )
+        .await;
+        let is_all_failed = !prices_map.is_empty() && prices_map.values().all(|v| !v.is_ok());
+        prices_map.extend(oracle_prices);
+        pipeline.process(&mut prices_map);
+        let spreads = spreads.unwrap_or_else(|e| {
+            warn!("Failed to fetch venue spreads: {}", e);
+            HashMap::new()
+        });
+        // info!("{:#?}", prices_map);
+        // Merge so streamed keys are kept, streamed pairs get their spread too.
+        price_sender.send_modify(|prices| {
+            prices.extend(prices_map);
+            changes.attach_changes(prices);
+            for (key, spread) in &spreads {
+                if let Some(TokenOrPairPriceInfo::Pair(pair)) = prices.get_mut(key) {
+                    pair.price_info.spread_bps = Some(spread.bps);
+                    let label = format!("{}/{}", pair.token_a.symbol, pair.token_b.symbol);
+                    if let Some(event) = divergence_monitor.record(key, &label, *spread) {
+                        let _ = monitors.divergence_sender.send(event);
+                    }
+                }
             }
-            None => {
-                retry_count += 1;
-                warn!("Price fetch failed (attempt {})", retry_count);
+        });
 
-                // Exponential backoff up to 5 minutes
-                let backoff = Duration::from_secs(30).mul_f32(2f32.powi(retry_count - 1));
-                sleep(backoff.min(Duration::from_secs(300))).await;
-                continue;
-            }
+        if is_all_failed {
+            retry_count += 1;
+            warn!("Price fetch failed (attempt {})", retry_count);
+
+            // Exponential backoff up to 5 minutes
+            let backoff = Duration::from_secs(30).mul_f32(2f32.powi(retry_count - 1));
+            wait_streaming(
+                backoff.min(Duration::from_secs(300)),
+                &mut stream_receiver,
+                &mut pipeline,
+                &mut changes,
+                &price_sender,
+            )
+            .await;
+            continue;
         }
+        retry_count = 0;
 
         // JUP Perps
         let wallet_address = match maybe_wallet_address {
@@ -65,28 +360,65 @@ pub async fn run_loop(
 
         println!("Fetching positions for wallet: {:?}", wallet_address);
         match perps_fetcher
-            .fetch_positions_pnl_and_format(wallet_address)
+            .fetch_open_positions(wallet_address, token_registry)
             .await
         {
-            Ok(positions_result) => {
+            Ok(positions) => {
                 retry_count = 0;
+
+                // Mark prices of the markets with open positions, for the distance to liquidation.
+                let mut markets = positions
+                    .iter()
+                    .map(|position| position.market.address.as_str())
+                    .collect::<Vec<_>>();
+                markets.sort();
+                markets.dedup();
+                // Markets priced this tick are reused, only the others are fetched.
+                let mut mark_prices = fresh_token_prices(&price_sender.borrow(), &markets);
+                let missing_markets = markets
+                    .into_iter()
+                    .filter(|market| !mark_prices.contains_key(*market))
+                    .collect::<Vec<_>>();
+                if !missing_markets.is_empty() {
+                    match mark_price_fetcher.fetch_many_prices(&missing_markets).await {
+                        Ok(prices) => mark_prices.extend(prices),
+                        Err(e) => warn!("No mark prices for liquidation checks: {}", e),
+                    }
+                }
+                for alert in liquidation_monitor.check(&positions, &mark_prices) {
+                    let _ = monitors.liquidation_sender.send(alert);
+                }
+
+                let positions_result = PositionPNLs::from_positions(&positions);
                 let mut prices_map: HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> =
                     HashMap::new();
-                let perps_key: TokenOrPairAddress = format!("{}_PERPS", sol_token.address.clone());
-                let id = format!("{}_PERPS", sol_token.symbol.clone());
-                let price = positions_result.total_pnl_usd;
-                let value_in_usd_info = TokenOrPairPriceInfo::Perp(PerpValueInfo {
-                    id,
-                    token: sol_token.clone(),
-                    pnl_after_fees_usd: PriceInfo {
-                        price: Some(price),
-                        formatted_price: format_price(price),
-                        updated_at: Utc::now().timestamp_millis() as u64,
-                    },
-                });
-                prices_map.insert(perps_key, value_in_usd_info);
+                // One entry per market with open positions.
+                for (market, pnls) in positions_result.by_market() {
+                    let key = perps_key(&market.address);
+                    let price = pnls.total_pnl_usd;
+                    let value_in_usd_info = TokenOrPairPriceInfo::Perp(PerpValueInfo {
+                        id: key.clone(),
+                        liquidation: liquidation_monitor.status(&market.address),
+                        token: market,
+                        pnl_after_fees_usd: PriceInfo {
+                            price: Some(price),
+                            formatted_price: format_price(price),
+                            updated_at: get_unix_timestamp(),
+                            source: Some(PriceSourceKind::Jupiter),
+                            ..Default::default()
+                        },
+                    });
+                    prices_map.insert(key, value_in_usd_info);
+                }
                 info!("{:#?}", prices_map);
-                price_sender.send(prices_map)?;
+                price_sender.send_modify(|prices| {
+                    // Markets whose positions all closed drop out.
+                    prices.retain(|key, info| {
+                        !matches!(info, TokenOrPairPriceInfo::Perp(_))
+                            || prices_map.contains_key(key)
+                    });
+                    prices.extend(prices_map)
+                });
             }
             Err(_) => {
                 retry_count += 1;
@@ -94,12 +426,115 @@ pub async fn run_loop(
 
                 // Exponential backoff up to 5 minutes
                 let backoff = Duration::from_secs(30).mul_f32(2f32.powi(retry_count - 1));
-                sleep(backoff.min(Duration::from_secs(300))).await;
+                wait_streaming(
+                    backoff.min(Duration::from_secs(300)),
+                    &mut stream_receiver,
+                    &mut pipeline,
+                    &mut changes,
+                    &price_sender,
+                )
+                .await;
                 continue;
             }
         }
 
         // Wait for next poll
-        sleep(POLL_INTERVAL).await;
+        wait_streaming(
+            POLL_INTERVAL,
+            &mut stream_receiver,
+            &mut pipeline,
+            &mut changes,
+            &price_sender,
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::change::ChangeSettings;
+    use crate::feeder::{PairPriceInfo, TokenPriceInfo};
+
+    fn pair_info(price: Option<f64>, spread_bps: Option<f64>) -> TokenOrPairPriceInfo {
+        TokenOrPairPriceInfo::Pair(PairPriceInfo {
+            price_info: PriceInfo {
+                price,
+                updated_at: get_unix_timestamp(),
+                spread_bps,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_fresh_token_prices() {
+        let token_info = |price: Option<f64>, is_stale: bool| {
+            TokenOrPairPriceInfo::Token(TokenPriceInfo {
+                price_info: PriceInfo {
+                    price,
+                    is_stale,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        let prices = HashMap::from([
+            ("sol".to_owned(), token_info(Some(150.0), false)),
+            ("eth".to_owned(), token_info(Some(2500.0), true)),
+            ("btc".to_owned(), token_info(None, false)),
+            ("pair".to_owned(), pair_info(Some(1.5), None)),
+        ]);
+
+        assert_eq!(
+            fresh_token_prices(&prices, &["sol", "eth", "btc", "pair", "jup"]),
+            HashMap::from([("sol".to_owned(), 150.0)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamed_prices_go_through_the_pipeline() {
+        let (_, seed_receiver) = mpsc::unbounded_channel();
+        let mut pipeline = PricePipeline::new(vec![], seed_receiver);
+        let mut changes = ChangeTracker::new(ChangeSettings::default());
+        let (price_sender, price_receiver) = watch::channel(HashMap::from([(
+            "pair".to_owned(),
+            pair_info(Some(1.0), Some(12.0)),
+        )]));
+        let (stream_sender, mut stream_receiver) = mpsc::unbounded_channel();
+
+        stream_sender
+            .send(("pair".to_owned(), pair_info(Some(1.5), None)))
+            .unwrap();
+        wait_streaming(
+            Duration::from_millis(50),
+            &mut stream_receiver,
+            &mut pipeline,
+            &mut changes,
+            &price_sender,
+        )
+        .await;
+        let streamed = price_receiver.borrow()["pair"].price_info().clone();
+        assert_eq!(streamed.price, Some(1.5));
+        assert_eq!(streamed.spread_bps, Some(12.0));
+        assert!(streamed.change.is_some());
+
+        // A failed update falls back to the last good price.
+        stream_sender
+            .send(("pair".to_owned(), pair_info(None, None)))
+            .unwrap();
+        drop(stream_sender);
+        wait_streaming(
+            Duration::from_millis(50),
+            &mut stream_receiver,
+            &mut pipeline,
+            &mut changes,
+            &price_sender,
+        )
+        .await;
+        let streamed = price_receiver.borrow()["pair"].price_info().clone();
+        assert_eq!(streamed.price, Some(1.5));
+        assert!(streamed.is_stale);
     }
 }
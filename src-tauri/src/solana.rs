@@ -0,0 +1,137 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::fetcher::{FetchError, Fetcher};
+
+pub const SOLANA_RPC: &str = "https://api.mainnet-beta.solana.com";
+
+#[derive(Serialize, Debug)]
+struct RpcRequest<'a, P> {
+    jsonrpc: &'a str,
+    id: u64,
+    method: &'a str,
+    params: P,
+}
+
+#[derive(Serialize, Debug)]
+struct AccountInfoConfig<'a> {
+    encoding: &'a str,
+    commitment: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct AccountInfoResult {
+    value: Option<AccountInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MultipleAccountsResult {
+    value: Vec<Option<AccountInfo>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AccountInfo {
+    // `[data, encoding]`
+    data: (String, String),
+}
+
+impl AccountInfo {
+    fn decode(&self) -> Result<Vec<u8>, FetchError> {
+        STANDARD
+            .decode(&self.data.0)
+            .map_err(|e| FetchError::Decode(e.to_string()))
+    }
+}
+
+const ACCOUNT_INFO_CONFIG: AccountInfoConfig = AccountInfoConfig {
+    encoding: "base64",
+    commitment: "confirmed",
+};
+
+/// Minimal Solana JSON-RPC client on top of the shared fetcher.
+#[derive(Clone)]
+pub struct SolanaRpc {
+    fetcher: Fetcher,
+    rpc_url: String,
+}
+
+impl Default for SolanaRpc {
+    fn default() -> Self {
+        Self::with_fetcher(Fetcher::new(), SOLANA_RPC)
+    }
+}
+
+impl SolanaRpc {
+    pub fn with_fetcher(fetcher: Fetcher, rpc_url: &str) -> Self {
+        Self {
+            fetcher,
+            rpc_url: rpc_url.to_owned(),
+        }
+    }
+
+    async fn call<P, T>(&self, method: &str, params: P) -> Result<T, FetchError>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        let request = RpcRequest {
+            jsonrpc: "2.0",
+            id: 1,
+            method,
+            params,
+        };
+
+        self.fetcher
+            .post_with_retry(&self.rpc_url, &request, |response: RpcResponse<T>| {
+                match (response.result, response.error) {
+                    (_, Some(error)) => Err(FetchError::Rpc {
+                        code: error.code,
+                        message: error.message,
+                    }),
+                    (Some(result), None) => Ok(result),
+                    (None, None) => Err(FetchError::Decode(format!("Empty {} response", method))),
+                }
+            })
+            .await
+    }
+
+    /// Raw data of an account, `NotFound` if it does not exist.
+    pub async fn get_account_info(&self, address: &str) -> Result<Vec<u8>, FetchError> {
+        let result: AccountInfoResult = self
+            .call("getAccountInfo", (address, ACCOUNT_INFO_CONFIG))
+            .await?;
+
+        result
+            .value
+            .ok_or_else(|| FetchError::NotFound(format!("Account {}", address)))?
+            .decode()
+    }
+
+    /// Raw data of many accounts in one call, in request order, `None` for missing ones.
+    pub async fn get_multiple_accounts(
+        &self,
+        addresses: &[&str],
+    ) -> Result<Vec<Option<Vec<u8>>>, FetchError> {
+        let result: MultipleAccountsResult = self
+            .call("getMultipleAccounts", (addresses, ACCOUNT_INFO_CONFIG))
+            .await?;
+
+        result
+            .value
+            .iter()
+            .map(|account| account.as_ref().map(AccountInfo::decode).transpose())
+            .collect()
+    }
+}
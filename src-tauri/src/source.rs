@@ -0,0 +1,317 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use strum_macros::{Display, EnumString};
+
+use crate::{
+    config::{ApiConfig, PriceMode},
+    feeder::{PairPriceInfo, PriceInfo, TokenOrPairAddress, TokenOrPairPriceInfo, TokenPriceInfo},
+    fetcher::Fetcher,
+    jup::{
+        prices::{PriceFetcher, TokenSymbol},
+        quote::{QuoteFetcher, QuotePriceSource},
+    },
+    pyth::{PythFetcher, PythPriceSource},
+    ray::{RaydiumFetcher, RaydiumPriceSource},
+    token_registry::{get_pair_ot_token_address_from_tokens, Token, TokenRegistry},
+};
+
+#[derive(Display, EnumString, Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum PriceSourceKind {
+    Jupiter,
+    JupiterQuote,
+    Raydium,
+    Pyth,
+    // Ratio of two USD prices, see `feeder::synthesize_pairs`.
+    Synthetic,
+}
+
+/// A backend able to price single tokens and token pairs.
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    fn kind(&self) -> PriceSourceKind;
+
+    /// Lower value is tried first.
+    fn priority(&self) -> u8;
+
+    /// Fetches whatever it can, missing keys are left for the next source.
+    async fn fetch_prices(
+        &self,
+        single_tokens: &[Token],
+        pairs: &[[Token; 2]],
+    ) -> Result<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>>;
+}
+
+/// Rotates across price sources by priority and falls back when one fails.
+pub struct PriceSources {
+    sources: Vec<Box<dyn PriceSource>>,
+}
+
+impl Default for PriceSources {
+    fn default() -> Self {
+        Self::new(vec![
+            Box::new(PriceFetcher::new()),
+            Box::new(RaydiumPriceSource::default()),
+        ])
+    }
+}
+
+impl PriceSources {
+    pub fn new(mut sources: Vec<Box<dyn PriceSource>>) -> Self {
+        sources.sort_by_key(|source| source.priority());
+        Self { sources }
+    }
+
+    /// Every source shares the given fetcher and takes its base URL from config.
+    /// In quote mode swap quotes go first, anything they can't price falls back to the mid price.
+    /// The oracle source reuses whatever `pyth_fetcher` last fetched.
+    pub fn from_config(
+        fetcher: &Fetcher,
+        config: &ApiConfig,
+        token_registry: &TokenRegistry,
+        pyth_fetcher: PythFetcher,
+    ) -> Self {
+        let mut sources: Vec<Box<dyn PriceSource>> = vec![];
+        let usd_token = token_registry
+            .stable_tokens
+            .iter()
+            .find(|token| token.symbol == TokenSymbol::USDC);
+        if let (PriceMode::Quote, Some(usd_token)) = (config.quote.mode, usd_token) {
+            // Same priority as the price API, sorting keeps it in front.
+            sources.push(Box::new(QuotePriceSource::new(
+                QuoteFetcher::with_fetcher(fetcher.clone(), &config.jup_quote_api)
+                    .with_slippage_bps(config.quote.slippage_bps),
+                usd_token.clone(),
+                config.quote.notionals.clone(),
+            )));
+        }
+
+        sources.push(Box::new(PriceFetcher::with_fetcher(
+            fetcher.clone(),
+            &config.jup_price_api,
+        )));
+        sources.push(Box::new(RaydiumPriceSource::new(
+            RaydiumFetcher::with_fetcher(fetcher.clone(), &config.raydium_api),
+        )));
+        sources.push(Box::new(PythPriceSource::new(pyth_fetcher)));
+
+        Self::new(sources)
+    }
+
+    pub fn kinds(&self) -> Vec<PriceSourceKind> {
+        self.sources.iter().map(|source| source.kind()).collect()
+    }
+
+    /// Asks each source in priority order for the keys that are still missing or failed.
+    /// Keys no source could price are kept with their error.
+    pub async fn fetch_many_price_and_format(
+        &self,
+        single_tokens: Vec<Token>,
+        pairs: Vec<[Token; 2]>,
+    ) -> HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> {
+        let mut all_prices: HashMap<TokenOrPairAddress, TokenOrPairPriceInfo> = HashMap::new();
+        let mut remaining_tokens = single_tokens;
+        let mut remaining_pairs = pairs;
+        let mut last_error = "No price source available".to_owned();
+
+        for source in &self.sources {
+            if remaining_tokens.is_empty() && remaining_pairs.is_empty() {
+                break;
+            }
+
+            match source
+                .fetch_prices(&remaining_tokens, &remaining_pairs)
+                .await
+            {
+                Ok(prices) => {
+                    for (address, info) in prices {
+                        if info.is_ok() || !all_prices.contains_key(&address) {
+                            all_prices.insert(address, info);
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("{} price source failed: {}", source.kind(), e);
+                    last_error = format!("{}: {}", source.kind(), e);
+                    continue;
+                }
+            }
+
+            let is_priced =
+                |address: &str| all_prices.get(address).is_some_and(|info| info.is_ok());
+            remaining_tokens.retain(|token| !is_priced(&token.address));
+            remaining_pairs.retain(|pair| {
+                get_pair_ot_token_address_from_tokens(pair)
+                    .map(|address| !is_priced(&address))
+                    .unwrap_or(true)
+            });
+        }
+
+        if !remaining_tokens.is_empty() || !remaining_pairs.is_empty() {
+            warn!(
+                "No price source could price {} tokens and {} pairs",
+                remaining_tokens.len(),
+                remaining_pairs.len()
+            );
+        }
+
+        for token in remaining_tokens {
+            all_prices.entry(token.address.clone()).or_insert_with(|| {
+                TokenOrPairPriceInfo::Token(TokenPriceInfo {
+                    token,
+                    price_info: PriceInfo::failed(&last_error),
+                })
+            });
+        }
+
+        for [token_a, token_b] in remaining_pairs {
+            let address = format!("{}_{}", token_a.address, token_b.address);
+            all_prices.entry(address).or_insert_with(|| {
+                TokenOrPairPriceInfo::Pair(PairPriceInfo {
+                    token_a,
+                    token_b,
+                    price_info: PriceInfo::failed(&last_error),
+                })
+            });
+        }
+
+        all_prices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token_registry::tests::token;
+    use anyhow::bail;
+
+    struct MockSource {
+        kind: PriceSourceKind,
+        priority: u8,
+        prices: Option<HashMap<String, f64>>,
+    }
+
+    #[async_trait]
+    impl PriceSource for MockSource {
+        fn kind(&self) -> PriceSourceKind {
+            self.kind
+        }
+
+        fn priority(&self) -> u8 {
+            self.priority
+        }
+
+        async fn fetch_prices(
+            &self,
+            single_tokens: &[Token],
+            _pairs: &[[Token; 2]],
+        ) -> Result<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>> {
+            let Some(prices) = &self.prices else {
+                bail!("{} is down", self.kind)
+            };
+
+            Ok(single_tokens
+                .iter()
+                .filter_map(|token| {
+                    prices.get(&token.address).map(|price| {
+                        (
+                            token.address.clone(),
+                            TokenOrPairPriceInfo::Token(TokenPriceInfo {
+                                token: token.clone(),
+                                price_info: PriceInfo {
+                                    price: Some(*price),
+                                    source: Some(self.kind),
+                                    ..Default::default()
+                                },
+                            }),
+                        )
+                    })
+                })
+                .collect())
+        }
+    }
+
+    fn source_of(info: &TokenOrPairPriceInfo) -> Option<PriceSourceKind> {
+        match info {
+            TokenOrPairPriceInfo::Token(info) => info.price_info.source,
+            _ => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_to_next_source_by_priority() {
+        let sources = PriceSources::new(vec![
+            Box::new(MockSource {
+                kind: PriceSourceKind::Raydium,
+                priority: 1,
+                prices: Some(HashMap::from([("sol".to_owned(), 200.0)])),
+            }),
+            Box::new(MockSource {
+                kind: PriceSourceKind::Jupiter,
+                priority: 0,
+                prices: None,
+            }),
+        ]);
+        assert_eq!(
+            sources.kinds(),
+            vec![PriceSourceKind::Jupiter, PriceSourceKind::Raydium]
+        );
+
+        let prices = sources
+            .fetch_many_price_and_format(vec![token("sol", TokenSymbol::SOL)], vec![])
+            .await;
+
+        assert_eq!(source_of(&prices["sol"]), Some(PriceSourceKind::Raydium));
+    }
+
+    #[tokio::test]
+    async fn test_missing_keys_are_filled_by_next_source() {
+        let sources = PriceSources::new(vec![
+            Box::new(MockSource {
+                kind: PriceSourceKind::Jupiter,
+                priority: 0,
+                prices: Some(HashMap::from([("sol".to_owned(), 200.0)])),
+            }),
+            Box::new(MockSource {
+                kind: PriceSourceKind::Raydium,
+                priority: 1,
+                prices: Some(HashMap::from([
+                    ("sol".to_owned(), 201.0),
+                    ("jup".to_owned(), 1.0),
+                ])),
+            }),
+        ]);
+
+        let prices = sources
+            .fetch_many_price_and_format(
+                vec![
+                    token("sol", TokenSymbol::SOL),
+                    token("jup", TokenSymbol::JUP),
+                ],
+                vec![],
+            )
+            .await;
+
+        assert_eq!(source_of(&prices["sol"]), Some(PriceSourceKind::Jupiter));
+        assert_eq!(source_of(&prices["jup"]), Some(PriceSourceKind::Raydium));
+    }
+
+    #[tokio::test]
+    async fn test_failed_keys_are_kept_with_error() {
+        let sources = PriceSources::new(vec![Box::new(MockSource {
+            kind: PriceSourceKind::Jupiter,
+            priority: 0,
+            prices: None,
+        })]);
+
+        let prices = sources
+            .fetch_many_price_and_format(vec![token("sol", TokenSymbol::SOL)], vec![])
+            .await;
+
+        let sol = prices["sol"].price_info();
+        assert_eq!(sol.price, None);
+        assert_eq!(sol.error.as_deref(), Some("Jupiter: Jupiter is down"));
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, sync::Mutex};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+
+use crate::{
+    feeder::{TokenOrPairAddress, TokenOrPairPriceInfo},
+    time::get_unix_timestamp,
+};
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+// How often retention runs while streaming ticks in.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// One stored price, `time` is the unix timestamp in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tick {
+    pub key: TokenOrPairAddress,
+    pub time: u64,
+    pub price: f64,
+    pub source: Option<String>,
+}
+
+/// How long ticks are kept, e.g.
+///
+/// ```yaml
+/// retention:
+///   raw_days: 7
+///   downsample_secs: 3600
+///   max_days: 365
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    // Every tick is kept this long.
+    pub raw_days: u64,
+    // Older ticks are thinned to the last one per bucket of this size.
+    pub downsample_secs: u64,
+    // Anything older is dropped, zero keeps everything.
+    pub max_days: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            raw_days: 7,
+            downsample_secs: 60 * 60,
+            max_days: 365,
+        }
+    }
+}
+
+/// Price history in a local SQLite database.
+pub struct PriceStore {
+    conn: Mutex<Connection>,
+}
+
+impl PriceStore {
+    /// Opens or creates the database, e.g. `{app_data_dir}/prices.sqlite`.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let conn =
+            Connection::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS ticks (
+                key TEXT NOT NULL,
+                time INTEGER NOT NULL,
+                price REAL NOT NULL,
+                source TEXT,
+                PRIMARY KEY (key, time)
+            );",
+        )?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Appends every fresh price, returns how many ticks were new.
+    /// The same key and `updated_at` is stored once, so resent maps are cheap.
+    pub fn append(
+        &self,
+        prices: &HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    ) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut inserted = 0;
+        {
+            let mut statement = tx.prepare_cached(
+                "INSERT OR IGNORE INTO ticks (key, time, price, source) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (key, info) in prices {
+                let price_info = info.price_info();
+                // Failed fetches have nothing new and stale ones repeat an old tick.
+                let (Some(price), false) = (price_info.price, price_info.is_stale) else {
+                    continue;
+                };
+                inserted += statement.execute(params![
+                    key,
+                    price_info.updated_at,
+                    price,
+                    price_info.source.map(|source| source.to_string()),
+                ])?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(inserted)
+    }
+
+    /// Ticks of `key` from `time_from` to `time_to` inclusive, oldest first.
+    pub fn query(&self, key: &str, time_from: u64, time_to: u64) -> Result<Vec<Tick>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare_cached(
+            "SELECT key, time, price, source FROM ticks
+             WHERE key = ?1 AND time BETWEEN ?2 AND ?3
+             ORDER BY time",
+        )?;
+        let ticks = statement
+            .query_map(params![key, time_from, time_to], |row| {
+                Ok(Tick {
+                    key: row.get(0)?,
+                    time: row.get(1)?,
+                    price: row.get(2)?,
+                    source: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ticks)
+    }
+
+    /// Downsamples and drops old ticks, returns how many were removed.
+    pub fn apply_retention(&self, policy: &RetentionPolicy, now: u64) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let mut removed = 0;
+
+        if policy.max_days > 0 {
+            let expired_before = now.saturating_sub(policy.max_days * DAY_SECS);
+            removed += conn.execute("DELETE FROM ticks WHERE time < ?1", [expired_before])?;
+        }
+
+        if policy.downsample_secs > 0 {
+            // Keep the last tick of each bucket, like a candle close.
+            let raw_after = now.saturating_sub(policy.raw_days * DAY_SECS);
+            removed += conn.execute(
+                "DELETE FROM ticks WHERE time < ?1 AND (key, time) NOT IN (
+                    SELECT key, MAX(time) FROM ticks WHERE time < ?1
+                    GROUP BY key, time / ?2
+                )",
+                params![raw_after, policy.downsample_secs],
+            )?;
+        }
+
+        Ok(removed)
+    }
+
+    /// Stores every price update until the sender is dropped, applying retention hourly.
+    pub async fn run(
+        &self,
+        mut price_receiver: watch::Receiver<HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>>,
+        policy: RetentionPolicy,
+    ) {
+        let mut last_retention: Option<Instant> = None;
+
+        while price_receiver.changed().await.is_ok() {
+            let prices = price_receiver.borrow_and_update().clone();
+            if let Err(e) = self.append(&prices) {
+                warn!("Failed to store prices: {}", e);
+            }
+
+            if last_retention.is_none_or(|at| at.elapsed() >= RETENTION_INTERVAL) {
+                last_retention = Some(Instant::now());
+                match self.apply_retention(&policy, get_unix_timestamp()) {
+                    Ok(removed) if removed > 0 => info!("Retention removed {} ticks", removed),
+                    Ok(_) => {}
+                    Err(e) => warn!("Failed to apply retention: {}", e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feeder::{PriceInfo, TokenPriceInfo},
+        source::PriceSourceKind,
+        token_registry::Token,
+    };
+
+    fn tick(time: u64, price: Option<f64>) -> TokenOrPairPriceInfo {
+        TokenOrPairPriceInfo::Token(TokenPriceInfo {
+            token: Token::default(),
+            price_info: PriceInfo {
+                price,
+                updated_at: time,
+                source: Some(PriceSourceKind::Jupiter),
+                ..Default::default()
+            },
+        })
+    }
+
+    #[test]
+    fn test_append_and_query() {
+        let store = PriceStore::open_in_memory().unwrap();
+
+        assert_eq!(
+            store
+                .append(&HashMap::from([
+                    ("sol".to_owned(), tick(100, Some(170.0))),
+                    ("jup".to_owned(), tick(100, None)),
+                ]))
+                .unwrap(),
+            1
+        );
+        // Resending the same tick is a no-op.
+        assert_eq!(
+            store
+                .append(&HashMap::from([("sol".to_owned(), tick(100, Some(170.0)))]))
+                .unwrap(),
+            0
+        );
+        store
+            .append(&HashMap::from([("sol".to_owned(), tick(105, Some(171.0)))]))
+            .unwrap();
+
+        let ticks = store.query("sol", 0, 200).unwrap();
+        assert_eq!(
+            ticks.iter().map(|t| (t.time, t.price)).collect::<Vec<_>>(),
+            vec![(100, 170.0), (105, 171.0)]
+        );
+        assert_eq!(ticks[0].source.as_deref(), Some("Jupiter"));
+        assert!(store.query("sol", 101, 104).unwrap().is_empty());
+        assert!(store.query("jup", 0, 200).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retention_downsamples_then_expires() {
+        let store = PriceStore::open_in_memory().unwrap();
+        let now = 400 * DAY_SECS;
+        let policy = RetentionPolicy::default();
+
+        let old = now - 30 * DAY_SECS;
+        let times = [
+            // Expired
+            now - 366 * DAY_SECS,
+            // Same hour, only the last one stays.
+            old,
+            old + 60,
+            old + 120,
+            // Raw
+            now - 60,
+            now - 30,
+        ];
+        for (i, time) in times.iter().enumerate() {
+            store
+                .append(&HashMap::from([(
+                    "sol".to_owned(),
+                    tick(*time, Some(i as f64)),
+                )]))
+                .unwrap();
+        }
+
+        assert_eq!(store.apply_retention(&policy, now).unwrap(), 3);
+        let ticks = store.query("sol", 0, now).unwrap();
+        assert_eq!(
+            ticks.iter().map(|t| t.time).collect::<Vec<_>>(),
+            vec![old + 120, now - 60, now - 30]
+        );
+    }
+
+    #[test]
+    fn test_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("catbot-store-{}.sqlite", std::process::id()));
+        {
+            let store = PriceStore::open(&path).unwrap();
+            store
+                .append(&HashMap::from([("sol".to_owned(), tick(100, Some(170.0)))]))
+                .unwrap();
+        }
+
+        let store = PriceStore::open(&path).unwrap();
+        assert_eq!(store.query("sol", 0, 200).unwrap().len(), 1);
+
+        drop(store);
+        let _ = std::fs::remove_file(&path);
+    }
+}
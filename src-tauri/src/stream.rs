@@ -0,0 +1,437 @@
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout, Duration};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::{
+    feeder::{TokenOrPairAddress, TokenOrPairPriceInfo},
+    ray::clmm::{find_pool, ClmmPoolDecoder},
+    token_registry::{get_pair_ot_token_address_from_tokens, TokenRegistry},
+};
+
+/// Turns raw account data into a price for one key.
+pub trait AccountDecoder: Send + Sync {
+    fn decode(&self, data: &[u8]) -> Result<TokenOrPairPriceInfo>;
+}
+
+/// One on-chain account to watch and the price key it feeds.
+#[derive(Clone)]
+pub struct AccountSubscription {
+    pub key: TokenOrPairAddress,
+    pub account: String,
+    pub decoder: Arc<dyn AccountDecoder>,
+}
+
+/// Accounts we can stream for the tokens and pairs in the registry.
+pub fn stream_subscriptions(token_registry: &TokenRegistry) -> Vec<AccountSubscription> {
+    // Pairs traded by a known CLMM pool.
+    token_registry
+        .pairs
+        .iter()
+        .filter_map(|pair| {
+            let [base, quote] = pair;
+            let pool_id = find_pool(base, quote)?;
+            Some(AccountSubscription {
+                key: get_pair_ot_token_address_from_tokens(pair).ok()?,
+                account: pool_id.to_string(),
+                decoder: Arc::new(ClmmPoolDecoder {
+                    base: base.clone(),
+                    quote: quote.clone(),
+                }),
+            })
+        })
+        .collect()
+}
+
+/// Keys currently fed by the socket, the poller skips them.
+#[derive(Clone, Default)]
+pub struct LiveKeys(Arc<Mutex<HashSet<TokenOrPairAddress>>>);
+
+impl LiveKeys {
+    pub fn contains(&self, key: &str) -> bool {
+        self.0.lock().unwrap().contains(key)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    fn insert(&self, key: TokenOrPairAddress) {
+        self.0.lock().unwrap().insert(key);
+    }
+
+    fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcMessage {
+    id: Option<u64>,
+    result: Option<Value>,
+    error: Option<Value>,
+    method: Option<String>,
+    params: Option<NotificationParams>,
+}
+
+#[derive(Deserialize)]
+struct NotificationParams {
+    subscription: u64,
+    result: NotificationResult,
+}
+
+#[derive(Deserialize)]
+struct NotificationResult {
+    value: Option<AccountValue>,
+}
+
+#[derive(Deserialize)]
+struct AccountValue {
+    // `[data, encoding]`
+    data: (String, String),
+}
+
+/// Streams account updates over Solana RPC `accountSubscribe`.
+pub struct PriceStream {
+    ws_url: String,
+    subscriptions: Vec<AccountSubscription>,
+    reconnect_delay: Duration,
+    max_reconnect_delay: Duration,
+    // A quiet socket is pinged after this long and dropped if still quiet after as long again.
+    ping_interval: Duration,
+}
+
+impl PriceStream {
+    pub fn new(ws_url: &str, subscriptions: Vec<AccountSubscription>) -> Self {
+        Self {
+            ws_url: ws_url.to_owned(),
+            subscriptions,
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(60),
+            ping_interval: Duration::from_secs(30),
+        }
+    }
+
+    pub fn with_ping_interval(mut self, ping_interval: Duration) -> Self {
+        self.ping_interval = ping_interval;
+        self
+    }
+
+    pub fn with_reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self.reconnect_delay = reconnect_delay;
+        self
+    }
+
+    /// Keeps the socket up forever, resubscribing on every reconnect.
+    /// Prices go to the runner, so they get the same fallback, candles and indicators as polled ones.
+    pub async fn run(
+        self,
+        update_sender: mpsc::UnboundedSender<(TokenOrPairAddress, TokenOrPairPriceInfo)>,
+        live_keys: LiveKeys,
+    ) {
+        let mut retries = 0;
+
+        loop {
+            match self.run_once(&update_sender, &live_keys).await {
+                Ok(()) => warn!("Price stream closed"),
+                Err(e) => warn!("Price stream failed: {}", e),
+            }
+            if update_sender.is_closed() {
+                return;
+            }
+
+            // A session that got subscribed was healthy, start the backoff over.
+            if !live_keys.is_empty() {
+                retries = 0;
+            }
+
+            // Back to polling until resubscribed.
+            live_keys.clear();
+
+            let delay = self.reconnect_delay * 2u32.pow(retries.min(6));
+            sleep(delay.min(self.max_reconnect_delay)).await;
+            retries += 1;
+        }
+    }
+
+    async fn run_once(
+        &self,
+        update_sender: &mpsc::UnboundedSender<(TokenOrPairAddress, TokenOrPairPriceInfo)>,
+        live_keys: &LiveKeys,
+    ) -> Result<()> {
+        let (mut socket, _) = connect_async(&self.ws_url)
+            .await
+            .context("Failed to connect")?;
+
+        // Request id is the index of the subscription.
+        for (id, subscription) in self.subscriptions.iter().enumerate() {
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "accountSubscribe",
+                "params": [
+                    subscription.account,
+                    { "encoding": "base64", "commitment": "confirmed" }
+                ]
+            });
+            socket
+                .send(Message::Text(request.to_string().into()))
+                .await?;
+        }
+
+        // Subscription id from the server to index of the subscription.
+        let mut subscription_ids: HashMap<u64, usize> = HashMap::new();
+
+        // Half-open sockets never end on their own, anything from the server proves it alive.
+        let mut is_ping_pending = false;
+        loop {
+            let message = match timeout(self.ping_interval, socket.next()).await {
+                Ok(Some(message)) => message?,
+                Ok(None) => break,
+                Err(_) if is_ping_pending => {
+                    bail!("No answer to ping in {:?}", self.ping_interval)
+                }
+                Err(_) => {
+                    socket.send(Message::Ping(Default::default())).await?;
+                    is_ping_pending = true;
+                    continue;
+                }
+            };
+            is_ping_pending = false;
+
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            let message = match serde_json::from_str::<RpcMessage>(text.as_str()) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("Unexpected stream message: {}", e);
+                    continue;
+                }
+            };
+
+            if let (Some(id), Some(subscription)) = (
+                message.id,
+                message
+                    .id
+                    .and_then(|id| self.subscriptions.get(id as usize)),
+            ) {
+                match (message.result.and_then(|v| v.as_u64()), message.error) {
+                    (Some(subscription_id), _) => {
+                        info!(
+                            "Streaming {} from {}",
+                            subscription.key, subscription.account
+                        );
+                        subscription_ids.insert(subscription_id, id as usize);
+                        live_keys.insert(subscription.key.clone());
+                    }
+                    (None, error) => {
+                        warn!("Failed to subscribe {}: {:?}", subscription.account, error)
+                    }
+                }
+                continue;
+            }
+
+            let (Some("accountNotification"), Some(params)) =
+                (message.method.as_deref(), message.params)
+            else {
+                continue;
+            };
+            let Some(subscription) = subscription_ids
+                .get(&params.subscription)
+                .and_then(|index| self.subscriptions.get(*index))
+            else {
+                continue;
+            };
+            let Some(value) = params.result.value else {
+                continue;
+            };
+
+            let decoded = STANDARD
+                .decode(&value.data.0)
+                .context("Invalid base64")
+                .and_then(|data| subscription.decoder.decode(&data));
+            match decoded {
+                Ok(info) => {
+                    // The runner is gone, nothing left to feed.
+                    if update_sender
+                        .send((subscription.key.clone(), info))
+                        .is_err()
+                    {
+                        return Ok(());
+                    }
+                }
+                Err(e) => warn!("Failed to decode {}: {}", subscription.account, e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        feeder::{PriceInfo, TokenPriceInfo},
+        token_registry::Token,
+    };
+    use std::sync::Arc;
+    use tokio::{net::TcpListener, sync::Notify, time::timeout};
+    use tokio_tungstenite::accept_async;
+
+    /// Account data is the price as text.
+    struct TextDecoder;
+
+    impl AccountDecoder for TextDecoder {
+        fn decode(&self, data: &[u8]) -> Result<TokenOrPairPriceInfo> {
+            Ok(TokenOrPairPriceInfo::Token(TokenPriceInfo {
+                token: Token::default(),
+                price_info: PriceInfo {
+                    price: Some(std::str::from_utf8(data)?.parse()?),
+                    ..Default::default()
+                },
+            }))
+        }
+    }
+
+    /// Stands in for the RPC node, each connection pushes one price and waits for `close`.
+    async fn serve_ws(prices: Vec<&'static str>, close: Arc<Notify>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            for (subscription_id, price) in prices.into_iter().enumerate() {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut ws = accept_async(socket).await.unwrap();
+
+                let request = ws.next().await.unwrap().unwrap();
+                let request: Value = serde_json::from_str(request.to_text().unwrap()).unwrap();
+                assert_eq!(request["method"], "accountSubscribe");
+                assert_eq!(request["params"][0], "pool");
+
+                let confirm =
+                    json!({ "jsonrpc": "2.0", "result": subscription_id, "id": request["id"] });
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "accountNotification",
+                    "params": {
+                        "result": {
+                            "context": { "slot": 1 },
+                            "value": { "data": [STANDARD.encode(price), "base64"], "lamports": 1 }
+                        },
+                        "subscription": subscription_id
+                    }
+                });
+                ws.send(Message::Text(confirm.to_string().into()))
+                    .await
+                    .unwrap();
+                ws.send(Message::Text(notification.to_string().into()))
+                    .await
+                    .unwrap();
+
+                close.notified().await;
+                let _ = ws.close(None).await;
+            }
+        });
+
+        format!("ws://{address}")
+    }
+
+    #[tokio::test]
+    async fn test_silent_socket_falls_back_to_polling() {
+        // Never closed and never read again, so pings go unanswered.
+        let close = Arc::new(Notify::new());
+        let ws_url = serve_ws(vec!["1.5"], close).await;
+
+        let (update_sender, mut update_receiver) = mpsc::unbounded_channel();
+        let live_keys = LiveKeys::default();
+        let stream = PriceStream::new(
+            &ws_url,
+            vec![AccountSubscription {
+                key: "sol".to_owned(),
+                account: "pool".to_owned(),
+                decoder: Arc::new(TextDecoder),
+            }],
+        )
+        .with_ping_interval(Duration::from_millis(100));
+        let handle = tokio::spawn(stream.run(update_sender, live_keys.clone()));
+
+        wait_for_price(&mut update_receiver, 1.5).await;
+        assert!(live_keys.contains("sol"));
+
+        timeout(Duration::from_secs(2), async {
+            while live_keys.contains("sol") {
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        handle.abort();
+    }
+
+    async fn wait_for_price(
+        update_receiver: &mut mpsc::UnboundedReceiver<(TokenOrPairAddress, TokenOrPairPriceInfo)>,
+        price: f64,
+    ) {
+        timeout(Duration::from_secs(5), async {
+            while let Some((key, info)) = update_receiver.recv().await {
+                if key == "sol" && info.price_info().price == Some(price) {
+                    return;
+                }
+            }
+            panic!("Stream stopped");
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stream_reconnects_and_resubscribes() {
+        let close = Arc::new(Notify::new());
+        let ws_url = serve_ws(vec!["1.5", "2.5"], close.clone()).await;
+
+        let (update_sender, mut update_receiver) = mpsc::unbounded_channel();
+        let live_keys = LiveKeys::default();
+        let stream = PriceStream::new(
+            &ws_url,
+            vec![AccountSubscription {
+                key: "sol".to_owned(),
+                account: "pool".to_owned(),
+                decoder: Arc::new(TextDecoder),
+            }],
+        )
+        .with_reconnect_delay(Duration::from_millis(200));
+        let handle = tokio::spawn(stream.run(update_sender, live_keys.clone()));
+
+        wait_for_price(&mut update_receiver, 1.5).await;
+        assert!(live_keys.contains("sol"));
+
+        // Socket drops, the key goes back to polling.
+        close.notify_one();
+        timeout(Duration::from_secs(1), async {
+            while live_keys.contains("sol") {
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        wait_for_price(&mut update_receiver, 2.5).await;
+        assert!(live_keys.contains("sol"));
+
+        handle.abort();
+    }
+}
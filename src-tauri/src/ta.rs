@@ -0,0 +1,458 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    candle::{Candle, Interval},
+    feeder::{TokenOrPairAddress, TokenOrPairPriceInfo},
+};
+
+/// Simple moving average.
+#[derive(Debug, Clone)]
+pub struct Sma {
+    period: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Sma {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            window: VecDeque::new(),
+            sum: 0.0,
+        }
+    }
+
+    pub fn next(&mut self, value: f64) -> Option<f64> {
+        self.window.push_back(value);
+        self.sum += value;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+
+        (self.window.len() == self.period).then(|| self.sum / self.period as f64)
+    }
+}
+
+/// Exponential moving average, seeded with the SMA of the first `period` values.
+#[derive(Debug, Clone)]
+pub struct Ema {
+    alpha: f64,
+    seed: Sma,
+    value: Option<f64>,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        Self::with_alpha(period, 2.0 / (period.max(1) as f64 + 1.0))
+    }
+
+    /// Wilder's smoothing, as used by RSI and ATR.
+    pub fn wilder(period: usize) -> Self {
+        Self::with_alpha(period, 1.0 / period.max(1) as f64)
+    }
+
+    fn with_alpha(period: usize, alpha: f64) -> Self {
+        Self {
+            alpha,
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+
+    pub fn next(&mut self, value: f64) -> Option<f64> {
+        self.value = match self.value {
+            Some(previous) => Some(previous + self.alpha * (value - previous)),
+            None => self.seed.next(value),
+        };
+        self.value
+    }
+}
+
+/// Relative strength index with Wilder's smoothing.
+#[derive(Debug, Clone)]
+pub struct Rsi {
+    gain: Ema,
+    loss: Ema,
+    previous: Option<f64>,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Self {
+        Self {
+            gain: Ema::wilder(period),
+            loss: Ema::wilder(period),
+            previous: None,
+        }
+    }
+
+    pub fn next(&mut self, value: f64) -> Option<f64> {
+        let change = value - self.previous.replace(value)?;
+        // Both averages see every change, even while warming up.
+        let gain = self.gain.next(change.max(0.0));
+        let loss = self.loss.next((-change).max(0.0));
+        let (gain, loss) = gain.zip(loss)?;
+
+        Some(if loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + gain / loss)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MacdValue {
+    pub macd: f64,
+    pub signal: f64,
+    pub histogram: f64,
+}
+
+/// Moving average convergence divergence, usually 12, 26 and 9.
+#[derive(Debug, Clone)]
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+}
+
+impl Macd {
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Self {
+        Self {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+        }
+    }
+
+    pub fn next(&mut self, value: f64) -> Option<MacdValue> {
+        let fast = self.fast.next(value);
+        let slow = self.slow.next(value);
+        let macd = fast? - slow?;
+        let signal = self.signal.next(macd)?;
+
+        Some(MacdValue {
+            macd,
+            signal,
+            histogram: macd - signal,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bands {
+    pub upper: f64,
+    pub middle: f64,
+    pub lower: f64,
+}
+
+/// Bollinger Bands, SMA plus and minus `k` population standard deviations.
+#[derive(Debug, Clone)]
+pub struct BollingerBands {
+    period: usize,
+    k: f64,
+    window: VecDeque<f64>,
+}
+
+impl BollingerBands {
+    pub fn new(period: usize, k: f64) -> Self {
+        Self {
+            period: period.max(1),
+            k,
+            window: VecDeque::new(),
+        }
+    }
+
+    pub fn next(&mut self, value: f64) -> Option<Bands> {
+        self.window.push_back(value);
+        if self.window.len() > self.period {
+            self.window.pop_front();
+        }
+        if self.window.len() < self.period {
+            return None;
+        }
+
+        let n = self.period as f64;
+        let middle = self.window.iter().sum::<f64>() / n;
+        let variance = self
+            .window
+            .iter()
+            .map(|v| (v - middle).powi(2))
+            .sum::<f64>()
+            / n;
+        let width = self.k * variance.sqrt();
+
+        Some(Bands {
+            upper: middle + width,
+            middle,
+            lower: middle - width,
+        })
+    }
+}
+
+/// Average true range with Wilder's smoothing.
+#[derive(Debug, Clone)]
+pub struct Atr {
+    range: Ema,
+    previous_close: Option<f64>,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Self {
+        Self {
+            range: Ema::wilder(period),
+            previous_close: None,
+        }
+    }
+
+    pub fn next(&mut self, candle: &Candle) -> Option<f64> {
+        let true_range = match self.previous_close.replace(candle.close) {
+            Some(close) => (candle.high - candle.low)
+                .max((candle.high - close).abs())
+                .max((candle.low - close).abs()),
+            None => candle.high - candle.low,
+        };
+        self.range.next(true_range)
+    }
+}
+
+/// Latest value of every indicator, `None` while still warming up.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaSnapshot {
+    pub sma: Option<f64>,
+    pub ema: Option<f64>,
+    pub rsi: Option<f64>,
+    pub macd: Option<MacdValue>,
+    pub bollinger: Option<Bands>,
+    pub atr: Option<f64>,
+}
+
+/// Every indicator with the usual periods, fed candle by candle.
+#[derive(Debug, Clone)]
+pub struct Indicators {
+    sma: Sma,
+    ema: Ema,
+    rsi: Rsi,
+    macd: Macd,
+    bollinger: BollingerBands,
+    atr: Atr,
+    latest: TaSnapshot,
+}
+
+impl Default for Indicators {
+    fn default() -> Self {
+        Self {
+            sma: Sma::new(20),
+            ema: Ema::new(20),
+            rsi: Rsi::new(14),
+            macd: Macd::new(12, 26, 9),
+            bollinger: BollingerBands::new(20, 2.0),
+            atr: Atr::new(14),
+            latest: TaSnapshot::default(),
+        }
+    }
+}
+
+impl Indicators {
+    /// Updates on a closed candle.
+    pub fn next(&mut self, candle: &Candle) -> &TaSnapshot {
+        let close = candle.close;
+        self.latest = TaSnapshot {
+            sma: self.sma.next(close),
+            ema: self.ema.next(close),
+            rsi: self.rsi.next(close),
+            macd: self.macd.next(close),
+            bollinger: self.bollinger.next(close),
+            atr: self.atr.next(candle),
+        };
+        &self.latest
+    }
+
+    pub fn latest(&self) -> &TaSnapshot {
+        &self.latest
+    }
+
+    /// Snapshot after every candle of a history.
+    pub fn batch(candles: &[Candle]) -> Vec<TaSnapshot> {
+        let mut indicators = Self::default();
+        candles
+            .iter()
+            .map(|candle| indicators.next(candle).clone())
+            .collect()
+    }
+}
+
+/// Feeds bars of `interval` closed by the aggregator to their key's indicators
+/// and attaches the latest snapshot to every token and pair, perps are left alone.
+pub fn attach_indicators(
+    prices: &mut HashMap<TokenOrPairAddress, TokenOrPairPriceInfo>,
+    closed: &[(TokenOrPairAddress, Interval, Candle)],
+    interval: Interval,
+    indicators: &mut HashMap<TokenOrPairAddress, Indicators>,
+) {
+    for (address, _, candle) in closed.iter().filter(|(_, i, _)| *i == interval) {
+        indicators.entry(address.clone()).or_default().next(candle);
+    }
+
+    for (address, info) in prices.iter_mut() {
+        if let TokenOrPairPriceInfo::Perp(_) = info {
+            continue;
+        }
+        if let Some(indicators) = indicators.get(address) {
+            info.price_info_mut().ta = Some(indicators.latest().clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feeder::TokenPriceInfo;
+
+    // StockCharts' RSI example, 14 periods.
+    const RSI_CLOSES: [f64; 33] = [
+        44.34, 44.09, 44.15, 43.61, 44.33, 44.83, 45.10, 45.42, 45.84, 46.08, 45.89, 46.03, 45.61,
+        46.28, 46.28, 46.00, 46.03, 46.41, 46.22, 45.64, 46.21, 46.25, 45.71, 46.45, 45.78, 45.35,
+        44.03, 44.18, 44.22, 44.57, 43.42, 42.66, 43.13,
+    ];
+    // Their table rounds the averages to 2 decimals, these are the unrounded values.
+    const RSI_EXPECTED: [f64; 19] = [
+        70.46, 66.25, 66.48, 69.35, 66.29, 57.92, 62.88, 63.21, 56.01, 62.34, 54.67, 50.39, 40.02,
+        41.49, 41.90, 45.50, 37.32, 33.09, 37.79,
+    ];
+
+    fn candle(high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            high,
+            low,
+            close,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rsi_matches_reference() {
+        let mut rsi = Rsi::new(14);
+        let values = RSI_CLOSES
+            .iter()
+            .filter_map(|close| rsi.next(*close))
+            .collect::<Vec<_>>();
+
+        assert_eq!(values.len(), RSI_EXPECTED.len());
+        for (value, expected) in values.iter().zip(RSI_EXPECTED) {
+            assert!((value - expected).abs() < 0.005, "{value} != {expected}");
+        }
+    }
+
+    #[test]
+    fn test_moving_averages() {
+        let mut sma = Sma::new(3);
+        let smas = [1.0, 2.0, 3.0, 4.0].map(|v| sma.next(v));
+        assert_eq!(smas, [None, None, Some(2.0), Some(3.0)]);
+
+        // Seeded with the SMA, then 2 / (3 + 1) of each step.
+        let mut ema = Ema::new(3);
+        let emas = [1.0, 2.0, 3.0, 7.0].map(|v| ema.next(v));
+        assert_eq!(emas, [None, None, Some(2.0), Some(4.5)]);
+    }
+
+    #[test]
+    fn test_macd_of_a_ramp() {
+        // On a straight line each EMA lags by (period - 1) / 2, so MACD is 12.5 - 5.5.
+        let mut macd = Macd::new(12, 26, 9);
+        let values = (0..60)
+            .filter_map(|t| macd.next(t as f64))
+            .collect::<Vec<_>>();
+
+        assert_eq!(values.len(), 60 - 25 - 8);
+        let last = values.last().unwrap();
+        assert!((last.macd - 7.0).abs() < 1e-9);
+        assert!((last.signal - 7.0).abs() < 1e-9);
+        assert!(last.histogram.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bollinger_bands() {
+        let mut bollinger = BollingerBands::new(5, 2.0);
+        let bands = [1.0, 2.0, 3.0, 4.0, 5.0]
+            .map(|v| bollinger.next(v))
+            .last()
+            .copied()
+            .flatten()
+            .unwrap();
+
+        assert_eq!(bands.middle, 3.0);
+        assert!((bands.upper - (3.0 + 2.0 * 2f64.sqrt())).abs() < 1e-12);
+        assert!((bands.lower - (3.0 - 2.0 * 2f64.sqrt())).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_atr_uses_true_range() {
+        let mut atr = Atr::new(3);
+        let values = [
+            candle(11.0, 9.0, 10.0),
+            candle(11.0, 9.0, 10.0),
+            // Gaps up, the true range reaches back to the previous close.
+            candle(14.0, 13.0, 13.5),
+            candle(14.5, 12.5, 13.0),
+        ]
+        .map(|c| atr.next(&c));
+
+        assert_eq!(values[..2], [None, None]);
+        assert!((values[2].unwrap() - 8.0 / 3.0).abs() < 1e-12);
+        assert!((values[3].unwrap() - (8.0 / 3.0 * 2.0 + 2.0) / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_batch_matches_incremental() {
+        let candles = RSI_CLOSES
+            .iter()
+            .map(|close| candle(*close, *close, *close))
+            .collect::<Vec<_>>();
+
+        let snapshots = Indicators::batch(&candles);
+        let mut indicators = Indicators::default();
+        for candle in &candles {
+            indicators.next(candle);
+        }
+
+        assert_eq!(snapshots.last(), Some(indicators.latest()));
+        assert!((snapshots[14].rsi.unwrap() - 70.46).abs() < 0.005);
+        assert!(snapshots[18].sma.is_none() && snapshots[19].sma.is_some());
+    }
+
+    #[test]
+    fn test_attach_feeds_closed_candles_of_the_interval() {
+        let closed = RSI_CLOSES
+            .iter()
+            .enumerate()
+            .flat_map(|(i, close)| {
+                let candle = Candle::flat(i as u64 * 60, *close);
+                [
+                    ("sol".to_owned(), Interval::OneMinute, candle),
+                    // Other intervals must not move the 1m indicators.
+                    (
+                        "sol".to_owned(),
+                        Interval::FiveMinutes,
+                        Candle::flat(0, 1.0),
+                    ),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let mut prices = HashMap::from([(
+            "sol".to_owned(),
+            TokenOrPairPriceInfo::Token(TokenPriceInfo::default()),
+        )]);
+        let mut indicators = HashMap::new();
+
+        attach_indicators(
+            &mut prices,
+            &closed[..30],
+            Interval::OneMinute,
+            &mut indicators,
+        );
+
+        let rsi = prices["sol"].price_info().ta.as_ref().unwrap().rsi.unwrap();
+        assert!((rsi - 70.46).abs() < 0.005);
+    }
+}
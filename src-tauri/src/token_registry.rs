@@ -1,11 +1,16 @@
-use anyhow::{bail, Context};
+use anyhow::{anyhow, bail, Context};
 use serde::{Deserialize, Serialize};
 
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
 
 use crate::jup::prices::TokenSymbol;
 
+// Saved under the app data dir.
+pub const INVERTED_PAIRS_FILE: &str = "inverted_pairs.json";
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(deny_unknown_fields)]
 pub struct Token {
@@ -18,8 +23,17 @@ pub struct Token {
 #[derive(Debug, Default, Clone)]
 pub struct TokenRegistry {
     pub tokens: Vec<Token>,
+    // Priced with a direct `vsToken` request.
     pub pairs: Vec<[Token; 2]>,
+    // Priced from the USD prices of both tokens, no extra request.
+    pub synthetic_pairs: Vec<[Token; 2]>,
     pub stable_tokens: Vec<Token>,
+    // Markets traded on Jupiter perps.
+    pub perps_markets: Vec<Token>,
+    // Token address to its Pyth price account.
+    pub pyth_price_accounts: HashMap<String, String>,
+    // Pair addresses, in `pairs.json` order, the user shows the other way around.
+    pub inverted_pairs: HashSet<String>,
 }
 
 impl TokenRegistry {
@@ -27,17 +41,109 @@ impl TokenRegistry {
         let file_path = "./tokens/default.json";
         let stable_file_path = "./tokens/stable.json";
         let pairs_file_path = "./tokens/pairs.json";
+        let synthetic_pairs_file_path = "./tokens/synthetic_pairs.json";
+        let pyth_file_path = "./tokens/pyth.json";
+        let perps_file_path = "./tokens/perps.json";
         let tokens = Self::load_tokens(file_path).expect("Missing default.json");
         let stable_tokens = Self::load_tokens(stable_file_path).expect("Missing stable.json");
+        let perps_markets = Self::load_tokens(perps_file_path).expect("Missing perps.json");
         let pairs = Self::load_pairs(tokens.clone(), pairs_file_path).expect("Missing pairs.json");
+        let synthetic_pairs = Self::load_pairs(tokens.clone(), synthetic_pairs_file_path)
+            .expect("Missing synthetic_pairs.json");
+        let pyth_price_accounts =
+            Self::load_pyth_price_accounts(pyth_file_path).expect("Missing pyth.json");
 
         TokenRegistry {
             tokens,
             pairs,
+            synthetic_pairs,
             stable_tokens,
+            perps_markets,
+            pyth_price_accounts,
+            inverted_pairs: HashSet::new(),
         }
     }
 
+    /// Inverted pairs saved by `save_inverted_pairs`, none when the file doesn't exist yet.
+    pub fn load_inverted_pairs(file_path: &Path) -> anyhow::Result<HashSet<String>> {
+        if !file_path.exists() {
+            return Ok(HashSet::new());
+        }
+        let file = File::open(file_path).context("Failed to open file")?;
+        let reader = BufReader::new(file);
+        let inverted_pairs = serde_json::from_reader(reader)?;
+
+        Ok(inverted_pairs)
+    }
+
+    pub fn save_inverted_pairs(&self, file_path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = file_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut inverted_pairs = self.inverted_pairs.iter().collect::<Vec<_>>();
+        inverted_pairs.sort();
+        let file = File::create(file_path).context("Failed to create file")?;
+        serde_json::to_writer_pretty(file, &inverted_pairs)?;
+
+        Ok(())
+    }
+
+    /// Direct pairs then synthetic ones.
+    pub fn all_pairs(&self) -> impl Iterator<Item = &[Token; 2]> {
+        self.pairs.iter().chain(self.synthetic_pairs.iter())
+    }
+
+    /// The configured pair for an address in either order, and whether it was the inverted one.
+    pub fn find_pair(&self, address: &str) -> Option<(&[Token; 2], bool)> {
+        self.all_pairs().find_map(|pair| {
+            let pair_address = get_pair_ot_token_address_from_tokens(pair).ok()?;
+            if pair_address == address {
+                Some((pair, false))
+            } else if invert_pair_address(&pair_address).as_deref() == Some(address) {
+                Some((pair, true))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The address a token or pair is shown as, e.g. `SOL_JLP` for `JLP_SOL` once flipped.
+    pub fn display_address(&self, address: &str) -> String {
+        match self.find_pair(address) {
+            Some((pair, is_inverted)) => {
+                let pair_address =
+                    get_pair_ot_token_address_from_tokens(pair).unwrap_or(address.to_owned());
+                if is_inverted ^ self.inverted_pairs.contains(&pair_address) {
+                    invert_pair_address(&pair_address).unwrap_or(pair_address)
+                } else {
+                    pair_address
+                }
+            }
+            None => address.to_owned(),
+        }
+    }
+
+    /// Flips how a pair is shown, returns the address it is shown as now.
+    pub fn toggle_pair_inversion(&mut self, address: &str) -> anyhow::Result<String> {
+        let Some((pair, _)) = self.find_pair(address) else {
+            bail!("Not a configured pair: {}", address)
+        };
+        let pair_address = get_pair_ot_token_address_from_tokens(pair)?;
+        if !self.inverted_pairs.remove(&pair_address) {
+            self.inverted_pairs.insert(pair_address.clone());
+        }
+
+        Ok(self.display_address(&pair_address))
+    }
+
+    fn load_pyth_price_accounts(file_path: &str) -> anyhow::Result<HashMap<String, String>> {
+        let file = File::open(file_path).context("Failed to open file")?;
+        let reader = BufReader::new(file);
+        let price_accounts = serde_json::from_reader(reader)?;
+
+        Ok(price_accounts)
+    }
+
     fn load_tokens(file_path: &str) -> anyhow::Result<Vec<Token>> {
         let file = File::open(file_path).context("Failed to open file")?;
         let reader = BufReader::new(file);
@@ -73,6 +179,15 @@ impl TokenRegistry {
         self.tokens.iter().find(|token| token.address == address)
     }
 
+    /// Any known mint, tokens first then stables and perps markets.
+    pub fn get_by_mint(&self, address: &str) -> Option<&Token> {
+        self.tokens
+            .iter()
+            .chain(&self.stable_tokens)
+            .chain(&self.perps_markets)
+            .find(|token| token.address == address)
+    }
+
     pub fn get_by_symbol(&self, symbol: &TokenSymbol) -> Option<&Token> {
         self.tokens.iter().find(|token| token.symbol == *symbol)
     }
@@ -96,13 +211,23 @@ impl TokenRegistry {
     }
 
     pub fn get_tokens_from_pair_address(&self, address: &str) -> anyhow::Result<Vec<Token>> {
-        let tokens = if address.starts_with("SOL_PERPS") {
-            // TODO: support more token?
+        let tokens = if let Some(market_address) = address.strip_suffix("_PERPS") {
+            // PnL of a perps market, keyed apart from its price.
+            let market = self
+                .get_by_mint(market_address)
+                .ok_or_else(|| anyhow!("Unknown perps market {}", market_address))?;
+            vec![Token {
+                address: address.to_owned(),
+                ..market.clone()
+            }]
+        } else if let Some(token_address) = address.strip_suffix("_PYTH") {
+            // Oracle view of a single token, keyed apart from its market price.
+            let token = self
+                .get_by_address(token_address)
+                .ok_or_else(|| anyhow!("Unknown oracle token {}", token_address))?;
             vec![Token {
-                address: "So11111111111111111111111111111111111111112_PERPS".to_owned(),
-                symbol: TokenSymbol::SOL_PERPS,
-                name: address.to_owned(),
-                decimals: 9u8,
+                address: address.to_owned(),
+                ..token.clone()
             }]
         } else if address.contains("_") {
             self.get_by_pair_address(address).expect("Invalid address")
@@ -126,10 +251,27 @@ pub fn get_pair_ot_token_address_from_tokens(tokens: &[Token]) -> anyhow::Result
     Ok(address)
 }
 
+/// The same pair quoted the other way, e.g. `SOL_JLP` for `JLP_SOL`.
+pub fn invert_pair_address(address: &str) -> Option<String> {
+    match address.split('_').collect::<Vec<_>>()[..] {
+        [base, quote] if !matches!(quote, "PERPS" | "PYTH") => Some(format!("{}_{}", quote, base)),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    /// Token with a made up address, for mocks that don't need a real mint.
+    pub fn token(address: &str, symbol: TokenSymbol) -> Token {
+        Token {
+            address: address.to_owned(),
+            symbol,
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_token_registry_load_and_parse() {
         // Make test return Result
@@ -149,4 +291,106 @@ mod tests {
         assert!(!registry.tokens.is_empty());
         assert!(!registry.stable_tokens.is_empty());
     }
+
+    #[test]
+    fn test_oracle_keys() {
+        let registry = TokenRegistry::new();
+        let sol_pyth = registry
+            .get_tokens_from_pair_address("So11111111111111111111111111111111111111112_PYTH")
+            .unwrap();
+        assert_eq!(sol_pyth[0].symbol, TokenSymbol::SOL);
+        assert!(registry
+            .pyth_price_accounts
+            .contains_key("So11111111111111111111111111111111111111112"));
+        assert!(registry
+            .get_tokens_from_pair_address("unknown_PYTH")
+            .is_err());
+    }
+
+    #[test]
+    fn test_perps_markets() {
+        let registry = TokenRegistry::new();
+        let eth = registry
+            .get_by_mint("7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs")
+            .unwrap();
+        assert_eq!(eth.symbol, TokenSymbol::ETH);
+        let eth_perps = registry
+            .get_tokens_from_pair_address("7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs_PERPS")
+            .unwrap();
+        assert_eq!(eth_perps[0].symbol, TokenSymbol::ETH);
+        assert_eq!(
+            eth_perps[0].address,
+            "7vfCXTUXx5WJV5JADk17DUJ4ksgau7utNKj4b963voxs_PERPS"
+        );
+        assert_eq!(
+            registry
+                .get_by_mint("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")
+                .map(|token| token.symbol),
+            Some(TokenSymbol::USDC)
+        );
+        assert!(registry
+            .get_tokens_from_pair_address("unknown_PERPS")
+            .is_err());
+    }
+
+    #[test]
+    fn test_pair_inversion() {
+        let mut registry = TokenRegistry::new();
+        let jlp_sol = "27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4_So11111111111111111111111111111111111111112";
+        let sol_jlp = "So11111111111111111111111111111111111111112_27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4";
+
+        assert_eq!(invert_pair_address(jlp_sol).as_deref(), Some(sol_jlp));
+        assert_eq!(
+            invert_pair_address("So11111111111111111111111111111111111111112_PYTH"),
+            None
+        );
+        assert_eq!(
+            registry.find_pair(sol_jlp).map(|(_, inverted)| inverted),
+            Some(true)
+        );
+
+        assert_eq!(registry.display_address(jlp_sol), jlp_sol);
+        assert_eq!(registry.toggle_pair_inversion(jlp_sol).unwrap(), sol_jlp);
+        assert_eq!(registry.display_address(jlp_sol), sol_jlp);
+        // Either order flips the same pair back.
+        assert_eq!(registry.toggle_pair_inversion(sol_jlp).unwrap(), jlp_sol);
+        assert!(registry
+            .toggle_pair_inversion("So11111111111111111111111111111111111111112")
+            .is_err());
+    }
+
+    #[test]
+    fn test_synthetic_pairs() {
+        let registry = TokenRegistry::new();
+        let [base, quote] = &registry.synthetic_pairs[0];
+        assert_eq!(
+            (base.symbol, quote.symbol),
+            (TokenSymbol::JUP, TokenSymbol::JLP)
+        );
+
+        let jup_jlp = "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN_27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4";
+        assert!(registry.find_pair(jup_jlp).is_some());
+        assert_eq!(registry.all_pairs().count(), registry.pairs.len() + 1);
+    }
+
+    #[test]
+    fn test_inverted_pairs_survive_reload() {
+        let path =
+            std::env::temp_dir().join(format!("catbot-inverted-{}.json", std::process::id()));
+        let mut registry = TokenRegistry::new();
+        assert!(TokenRegistry::load_inverted_pairs(&path)
+            .unwrap()
+            .is_empty());
+
+        registry
+            .toggle_pair_inversion("27G8MtK7VtTcCHkpASjSDdkWWYfoqT6ggEuKidVJidD4_So11111111111111111111111111111111111111112")
+            .unwrap();
+        registry.save_inverted_pairs(&path).unwrap();
+
+        assert_eq!(
+            TokenRegistry::load_inverted_pairs(&path).unwrap(),
+            registry.inverted_pairs
+        );
+        let _ = std::fs::remove_file(&path);
+    }
 }
@@ -4,7 +4,10 @@ use tauri::{
     AppHandle,
 };
 
-use crate::{assets::read_local_image, jup::prices::TokenSymbol, token_registry::TokenRegistry};
+use crate::{
+    assets::read_local_image, feeder::PerpValueInfo, jup::prices::TokenSymbol, pyth::oracle_key,
+    token_registry::TokenRegistry,
+};
 
 fn get_menu_pair_item(
     app_handle: &AppHandle,
@@ -24,7 +27,12 @@ fn get_menu_pair_item(
     ];
     let pair_symbol = format!("{}_{}", pair[0].symbol, pair[1].symbol);
     let pair_address = format!("{}_{}", pair[0].address, pair[1].address);
-    let pair_label = format!("{}⋯{}", pair[0].symbol, pair[1].symbol);
+    // Id stays in `pairs.json` order, the label follows the user's choice.
+    let pair_label = if token_registry.inverted_pairs.contains(&pair_address) {
+        format!("{}⋯{}", pair[1].symbol, pair[0].symbol)
+    } else {
+        format!("{}⋯{}", pair[0].symbol, pair[1].symbol)
+    };
     let icon_path = format!("./tokens/{}.png", pair_symbol);
     let pair_icon = read_local_image(&icon_path).ok();
     let icon_menu_item = IconMenuItem::with_id(
@@ -39,7 +47,10 @@ fn get_menu_pair_item(
     Ok(icon_menu_item)
 }
 
-pub fn setup_tray(app_handle: &tauri::AppHandle) -> anyhow::Result<(TrayIconId, Menu<tauri::Wry>)> {
+pub fn setup_tray(
+    app_handle: &tauri::AppHandle,
+    token_registry: &TokenRegistry,
+) -> anyhow::Result<(TrayIconId, Menu<tauri::Wry>)> {
     // Portfolio
     let icon = read_local_image("../assets/jup-portfolio.png").ok();
     let portfolio_i = IconMenuItem::with_id(
@@ -51,16 +62,8 @@ pub fn setup_tray(app_handle: &tauri::AppHandle) -> anyhow::Result<(TrayIconId,
         None::<&str>,
     )?;
 
-    // Perps, TODO: support more tokens
-    let icon = read_local_image("./tokens/SOL_PERPS.png").ok();
-    let sol_perps_i = IconMenuItem::with_id(
-        app_handle,
-        "SOL_PERPS",
-        "SOL Perps",
-        true,
-        icon,
-        None::<&str>,
-    )?;
+    // Flip the selected pair, e.g. JLP/SOL to SOL/JLP
+    let flip_pair_i = MenuItem::with_id(app_handle, "flip_pair", "Flip Pair", true, None::<&str>)?;
 
     // Quit
     let quit_i = MenuItem::with_id(app_handle, "quit", "Quit", true, None::<&str>)?;
@@ -80,7 +83,6 @@ pub fn setup_tray(app_handle: &tauri::AppHandle) -> anyhow::Result<(TrayIconId,
     };
 
     // Default tokens
-    let token_registry = TokenRegistry::new();
     let tokens = token_registry.tokens.clone();
 
     // Menu
@@ -101,14 +103,37 @@ pub fn setup_tray(app_handle: &tauri::AppHandle) -> anyhow::Result<(TrayIconId,
         })
         .collect::<Result<_, _>>()?;
 
+    // Oracle, tokens with a Pyth feed
+    let oracle_menu_items: Vec<_> = tokens
+        .iter()
+        .filter(|token| {
+            token_registry
+                .pyth_price_accounts
+                .contains_key(&token.address)
+        })
+        .map(|token| {
+            let icon_path = format!("./tokens/{}.png", token.symbol);
+            let icon = read_local_image(&icon_path).ok();
+
+            IconMenuItem::with_id(
+                app_handle,
+                oracle_key(&token.address),
+                format!("{} Pyth", token.symbol),
+                true,
+                icon,
+                None::<&str>,
+            )
+        })
+        .collect::<Result<_, _>>()?;
+
     let menu = Menu::with_items(
         app_handle,
         &[
             &PredefinedMenuItem::separator(app_handle)?,
+            // Perps markets go right under the portfolio, see `sync_perps_menu_items`.
             &portfolio_i,
             &PredefinedMenuItem::separator(app_handle)?,
-            &sol_perps_i,
-            &PredefinedMenuItem::separator(app_handle)?,
+            &flip_pair_i,
             &settings_i,
             &PredefinedMenuItem::about(app_handle, None, Some(about_metadata))?,
             &PredefinedMenuItem::separator(app_handle)?,
@@ -123,19 +148,26 @@ pub fn setup_tray(app_handle: &tauri::AppHandle) -> anyhow::Result<(TrayIconId,
         .collect();
     let _ = menu.insert_items(&token_refs, 0);
 
+    // Oracle below tokens
+    let oracle_refs: Vec<&dyn IsMenuItem<tauri::Wry>> = oracle_menu_items
+        .iter()
+        .map(|item| item as &dyn IsMenuItem<tauri::Wry>)
+        .collect();
+    if !oracle_refs.is_empty() {
+        let _ = menu.insert_items(
+            &[&PredefinedMenuItem::separator(app_handle)?],
+            token_refs.len(),
+        );
+        let _ = menu.insert_items(&oracle_refs, token_refs.len() + 1);
+    }
+
     // Pairs
     let _ = menu.insert_items(&[&PredefinedMenuItem::separator(app_handle)?], 0);
     let items = token_registry
-        .pairs
-        .iter()
+        .all_pairs()
         .map(|pair| {
-            get_menu_pair_item(
-                app_handle,
-                &token_registry,
-                &pair[0].symbol,
-                &pair[1].symbol,
-            )
-            .expect("Invalid menu item")
+            get_menu_pair_item(app_handle, token_registry, &pair[0].symbol, &pair[1].symbol)
+                .expect("Invalid menu item")
         })
         .collect::<Vec<_>>();
 
@@ -163,3 +195,45 @@ pub fn setup_tray(app_handle: &tauri::AppHandle) -> anyhow::Result<(TrayIconId,
 
     Ok((tray_id, menu))
 }
+
+/// Keeps one item per perps market with open positions right under the portfolio.
+pub fn sync_perps_menu_items(
+    app_handle: &AppHandle,
+    menu: &Menu<tauri::Wry>,
+    perps: &[&PerpValueInfo],
+) -> anyhow::Result<()> {
+    // Markets whose positions all closed.
+    for item in menu.items()?.iter().filter(|item| {
+        let id = item.id().0.as_str();
+        id.ends_with("_PERPS") && !perps.iter().any(|perp| perp.id == id)
+    }) {
+        menu.remove(item)?;
+    }
+
+    let items = menu.items()?;
+    let Some(portfolio_index) = items.iter().position(|item| item.id().0 == "portfolio") else {
+        return Ok(());
+    };
+    let mut new_perps = perps
+        .iter()
+        .filter(|perp| !items.iter().any(|item| item.id().0 == perp.id))
+        .collect::<Vec<_>>();
+    // Each insert lands on top, so go backwards to keep them sorted.
+    new_perps.sort_by_key(|perp| std::cmp::Reverse(perp.token.symbol.to_string()));
+    for perp in new_perps {
+        let icon = read_local_image(&format!("./tokens/{}_PERPS.png", perp.token.symbol))
+            .or_else(|_| read_local_image(&format!("./tokens/{}.png", perp.token.symbol)))
+            .ok();
+        let item = IconMenuItem::with_id(
+            app_handle,
+            perp.id.clone(),
+            format!("{} Perps", perp.token.symbol),
+            true,
+            icon,
+            None::<&str>,
+        )?;
+        menu.insert(&item, portfolio_index + 1)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use anyhow::{bail, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{LN_2, PI};
+
+use crate::{
+    candle::{Candle, Interval},
+    jup::charts::CandleFetcher,
+    ray::{clmm::find_pool, PoolData, RaydiumFetcher, TimeFrameData},
+    time::get_unix_timestamp,
+    token_registry::{get_pair_ot_token_address_from_tokens, Token},
+};
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+// A week of hourly bars for the realized volatility.
+const HISTORY_INTERVAL: Interval = Interval::OneHour;
+const HISTORY_SECS: u64 = 7 * DAY_SECS;
+
+// Terms of the first exit series are dropped below this.
+const SERIES_EPSILON: f64 = 1e-12;
+const MAX_SERIES_TERMS: usize = 10_000;
+
+/// Standard deviation of log returns scaled to one day, `None` under 2 returns.
+pub fn realized_volatility(closes: &[f64], interval: Interval) -> Option<f64> {
+    let returns = closes
+        .windows(2)
+        .filter(|pair| pair[0] > 0.0 && pair[1] > 0.0)
+        .map(|pair| (pair[1] / pair[0]).ln())
+        .collect::<Vec<_>>();
+    if returns.len() < 2 {
+        return None;
+    }
+
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let periods_per_day = DAY_SECS as f64 / interval.secs() as f64;
+    Some((variance * periods_per_day).sqrt())
+}
+
+/// Parkinson estimate from the low and high over `days`, scaled to one day.
+pub fn parkinson_volatility(low: f64, high: f64, days: f64) -> Option<f64> {
+    if low <= 0.0 || high < low || days <= 0.0 {
+        return None;
+    }
+    let variance = (high / low).ln().powi(2) / (4.0 * LN_2);
+    Some((variance / days).sqrt())
+}
+
+/// Daily volatility from a Raydium pool's day, week and month ranges, their variances averaged.
+/// Inverting the pair doesn't change the high over low ratio, so either direction works.
+pub fn pool_volatility(pool: &PoolData) -> Option<f64> {
+    let windows: [(&TimeFrameData, f64); 3] =
+        [(&pool.day, 1.0), (&pool.week, 7.0), (&pool.month, 30.0)];
+    let variances = windows
+        .iter()
+        .filter_map(|(data, days)| parkinson_volatility(data.price_min, data.price_max, *days))
+        .map(|sigma| sigma * sigma)
+        .collect::<Vec<_>>();
+    if variances.is_empty() {
+        return None;
+    }
+    Some((variances.iter().sum::<f64>() / variances.len() as f64).sqrt())
+}
+
+/// Chance a driftless log price stays between `lower_pct` below and `upper_pct` above
+/// the current price for `days`, with `sigma` daily volatility.
+pub fn probability_in_range(sigma: f64, days: f64, lower_pct: f64, upper_pct: f64) -> f64 {
+    if lower_pct <= 0.0 || upper_pct <= 0.0 || lower_pct >= 100.0 {
+        return 0.0;
+    }
+    if sigma <= 0.0 || days <= 0.0 {
+        return 1.0;
+    }
+
+    // Distance to each barrier in log price.
+    let to_lower = -(1.0 - lower_pct / 100.0).ln();
+    let width = to_lower + (1.0 + upper_pct / 100.0).ln();
+    let variance = sigma * sigma * days;
+
+    // Survival of Brownian motion between two absorbing barriers, odd terms only.
+    let mut probability = 0.0;
+    for n in (1..MAX_SERIES_TERMS).step_by(2) {
+        let k = n as f64 * PI / width;
+        let decay = (-k * k * variance / 2.0).exp();
+        if decay < SERIES_EPSILON {
+            break;
+        }
+        probability += 4.0 / (n as f64 * PI) * (k * to_lower).sin() * decay;
+    }
+    probability.clamp(0.0, 1.0)
+}
+
+/// Daily volatility from each input, the blend averages the variances of what is known.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolatilityEstimate {
+    // From recent closes.
+    pub realized: Option<f64>,
+    // From the Raydium pool ranges.
+    pub parkinson: Option<f64>,
+}
+
+impl VolatilityEstimate {
+    pub fn daily(&self) -> Option<f64> {
+        let variances = [self.realized, self.parkinson]
+            .into_iter()
+            .flatten()
+            .map(|sigma| sigma * sigma)
+            .collect::<Vec<_>>();
+        if variances.is_empty() {
+            return None;
+        }
+        Some((variances.iter().sum::<f64>() / variances.len() as f64).sqrt())
+    }
+}
+
+/// How likely a pair stays in a band around its price, e.g. ±1% for 3 days.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RangeStability {
+    pub key: String,
+    pub price: f64,
+    pub range_pct: f64,
+    pub days: f64,
+    pub lower: f64,
+    pub upper: f64,
+    pub volatility: VolatilityEstimate,
+    // Chance the price never leaves the band over `days`.
+    pub probability_in_range: f64,
+}
+
+impl RangeStability {
+    pub fn new(
+        key: &str,
+        price: f64,
+        range_pct: f64,
+        days: f64,
+        volatility: VolatilityEstimate,
+    ) -> Result<Self> {
+        let Some(sigma) = volatility.daily() else {
+            bail!("No volatility for {}", key)
+        };
+
+        Ok(Self {
+            key: key.to_owned(),
+            price,
+            range_pct,
+            days,
+            lower: price * (1.0 - range_pct / 100.0),
+            upper: price * (1.0 + range_pct / 100.0),
+            probability_in_range: probability_in_range(sigma, days, range_pct, range_pct),
+            volatility,
+        })
+    }
+}
+
+/// Range stability of a pair from a week of hourly candles and its Raydium pool, when known.
+pub async fn pair_range_stability(
+    candle_fetcher: &CandleFetcher,
+    raydium_fetcher: &RaydiumFetcher,
+    pair: &[Token; 2],
+    range_pct: f64,
+    days: f64,
+) -> Result<RangeStability> {
+    let [base, quote] = pair;
+    let key = get_pair_ot_token_address_from_tokens(pair)?;
+    let time_to = get_unix_timestamp();
+    let candles = candle_fetcher
+        .fetch_candles(
+            base,
+            Some(quote),
+            HISTORY_INTERVAL,
+            time_to - HISTORY_SECS,
+            time_to,
+        )
+        .await?;
+    // The candles are enough on their own, the pool only sharpens the estimate.
+    let pool = match find_pool(base, quote) {
+        Some(pool_id) => match raydium_fetcher.fetch_pool_info_by_id(pool_id).await {
+            Ok(pool) => Some(pool),
+            Err(e) => {
+                warn!(
+                    "No Raydium pool for {}/{} volatility: {}",
+                    base.symbol, quote.symbol, e
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    let closes = candles.iter().map(|c| c.close).collect::<Vec<_>>();
+    let price = match (candles.last(), &pool) {
+        (Some(Candle { close, .. }), _) => *close,
+        (None, Some(pool)) => pool
+            .price_of(&base.address, &quote.address)
+            .unwrap_or_default(),
+        (None, None) => bail!("No price history for {}/{}", base.symbol, quote.symbol),
+    };
+
+    RangeStability::new(
+        &key,
+        price,
+        range_pct,
+        days,
+        VolatilityEstimate {
+            realized: realized_volatility(&closes, HISTORY_INTERVAL),
+            parkinson: pool.as_ref().and_then(pool_volatility),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fetcher::tests::fixture_fetcher,
+        ray::{PoolId, RAYDIUM_BASE_API},
+    };
+
+    #[test]
+    fn test_realized_volatility() {
+        // Steady growth has no volatility.
+        let steady = (0..10).map(|i| 1.01f64.powi(i)).collect::<Vec<_>>();
+        assert!(realized_volatility(&steady, Interval::OneDay).unwrap() < 1e-12);
+
+        // Up 1% then back, hourly.
+        let zigzag = (0..25)
+            .map(|i| if i % 2 == 0 { 100.0 } else { 101.0 })
+            .collect::<Vec<_>>();
+        let hourly_sd = (101.0f64 / 100.0).ln() * (24.0f64 / 23.0).sqrt();
+        let sigma = realized_volatility(&zigzag, Interval::OneHour).unwrap();
+        assert!((sigma - hourly_sd * 24f64.sqrt()).abs() < 1e-12);
+
+        assert_eq!(realized_volatility(&[1.0, 2.0], Interval::OneHour), None);
+    }
+
+    #[test]
+    fn test_probability_in_range() {
+        // P(max |W| < 1 up to t = 1) = 4/π Σ (-1)^k / (2k + 1) exp(-(2k + 1)² π² / 8)
+        let band_pct = (1.0f64.exp() - 1.0) * 100.0;
+        let lower_pct = (1.0 - (-1.0f64).exp()) * 100.0;
+        let probability = probability_in_range(1.0, 1.0, lower_pct, band_pct);
+        assert!((probability - 0.370777).abs() < 1e-5, "{probability}");
+
+        // Wider bands and shorter horizons are more likely to hold.
+        let narrow = probability_in_range(0.02, 3.0, 1.0, 1.0);
+        assert!(narrow < probability_in_range(0.02, 3.0, 5.0, 5.0));
+        assert!(narrow < probability_in_range(0.02, 1.0, 1.0, 1.0));
+        assert_eq!(probability_in_range(0.0, 3.0, 1.0, 1.0), 1.0);
+        assert_eq!(probability_in_range(0.02, 3.0, 0.0, 1.0), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_pool_volatility_replayed() {
+        let raydium_fetcher = RaydiumFetcher::with_fetcher(fixture_fetcher(), RAYDIUM_BASE_API);
+        let pool = raydium_fetcher
+            .fetch_pool_info_by_id(PoolId::SOL_JLP)
+            .await
+            .unwrap();
+
+        let day = parkinson_volatility(39.71, 40.42, 1.0).unwrap();
+        assert!((day - (40.42f64 / 39.71).ln() / (4.0 * LN_2).sqrt()).abs() < 1e-12);
+        let sigma = pool_volatility(&pool).unwrap();
+        assert!(sigma > 0.005 && sigma < 0.05, "{sigma}");
+
+        let stability = RangeStability::new(
+            "jlp_sol",
+            0.025,
+            1.0,
+            3.0,
+            VolatilityEstimate {
+                realized: None,
+                parkinson: Some(sigma),
+            },
+        )
+        .unwrap();
+        assert!((stability.upper - 0.02525).abs() < 1e-12);
+        assert!(stability.probability_in_range > 0.0 && stability.probability_in_range < 1.0);
+        assert!(RangeStability::new("jlp_sol", 0.025, 1.0, 3.0, Default::default()).is_err());
+    }
+}